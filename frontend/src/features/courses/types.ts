@@ -1,5 +1,6 @@
 export interface Course {
   id: string;
+  slug: string;
   title: string;
   subtitle?: string;
   description: string;
@@ -27,6 +28,25 @@ export interface Course {
   };
 }
 
+export interface CourseResource {
+  id: string;
+  title: string;
+  type: 'pdf' | 'project_source' | 'slide' | 'other';
+  file_size?: number;
+}
+
+export interface ChapterLesson {
+  id: string;
+  module_id: string;
+  title: string;
+  description?: string;
+  order_index: number;
+  duration_seconds: number;
+  is_free_preview: boolean;
+  has_video: boolean;
+  resources: CourseResource[];
+}
+
 export interface Chapter {
   id: string;
   course_id: string;
@@ -35,11 +55,23 @@ export interface Chapter {
   order_index: number;
   video_url?: string;
   duration_seconds?: number;
-  created_at: string;
+  lessons: ChapterLesson[];
+  created_at?: string;
+}
+
+export interface EnrollmentStatus {
+  enrolled: boolean;
+  enrolled_at?: string;
+  completed_lessons: number;
 }
 
 export interface CourseWithChapters extends Course {
   chapters: Chapter[];
+  resources: CourseResource[];
+  total_duration_seconds: number;
+  lessons_count: number;
+  // Absent quand l'utilisateur n'est pas connecté
+  enrollment?: EnrollmentStatus;
 }
 
 export interface Review {
@@ -269,7 +269,7 @@ export function Dashboard() {
                   <CardContent>
                     <div className="space-y-4">
                       {recentCourses.map((course) => (
-                        <Link key={course.id} to={`/courses/${course.id}`}>
+                        <Link key={course.id} to={`/courses/${course.slug}`}>
                           <div className="flex items-center gap-4 p-4 border rounded-lg hover:bg-gray-50 cursor-pointer">
                             <div className="w-16 h-16 bg-gray-200 rounded-lg flex items-center justify-center">
                               <BookOpen className="w-6 h-6 text-gray-400" />
@@ -113,7 +113,7 @@ export const CourseCard: React.FC<CourseCardProps> = ({ course, className }) =>
         </div>
 
         {/* Action button */}
-        <Link to={`/courses/${course.id}`} className="block">
+        <Link to={`/courses/${course.slug}`} className="block">
           <Button variant="primary" className="w-full">
             Commencer
           </Button>
@@ -0,0 +1,18 @@
+-- Champs du profil que l'utilisateur rend visibles sur sa page publique
+ALTER TABLE users ADD COLUMN IF NOT EXISTS public_profile_fields TEXT[] NOT NULL DEFAULT ARRAY['name', 'avatar_url', 'job_title', 'pcsoft_experience'];
+
+-- Avancement par leçon ; un cours est terminé quand toutes ses leçons le sont
+CREATE TABLE IF NOT EXISTS lesson_progress (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    lesson_id UUID NOT NULL REFERENCES lessons(id) ON DELETE CASCADE,
+    completed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, lesson_id)
+);
+
+-- Réglages modifiables par les admins
+CREATE TABLE IF NOT EXISTS app_settings (
+    key TEXT PRIMARY KEY,
+    value JSONB NOT NULL,
+    updated_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,9 @@
+-- Emails non essentiels que l'utilisateur accepte de recevoir ; sans ligne, les valeurs par défaut s'appliquent
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    course_announcements BOOLEAN NOT NULL DEFAULT true,
+    qa_replies BOOLEAN NOT NULL DEFAULT true,
+    marketing BOOLEAN NOT NULL DEFAULT false,
+    digests BOOLEAN NOT NULL DEFAULT true,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,23 @@
+-- Anciens slugs des cours renommés : les liens existants continuent de résoudre vers le cours
+CREATE TABLE IF NOT EXISTS course_slug_history (
+    slug TEXT PRIMARY KEY,
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    replaced_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS course_slug_history_course_id_idx ON course_slug_history(course_id);
+
+-- Alimentée à chaque changement de slug, quel que soit l'outil qui modifie le cours
+CREATE OR REPLACE FUNCTION record_course_slug_change() RETURNS trigger AS $$
+BEGIN
+    IF NEW.slug IS DISTINCT FROM OLD.slug THEN
+        INSERT INTO course_slug_history (slug, course_id) VALUES (OLD.slug, OLD.id)
+            ON CONFLICT (slug) DO UPDATE SET course_id = EXCLUDED.course_id, replaced_at = now();
+        -- Un slug redevenu actif n'est plus une redirection
+        DELETE FROM course_slug_history WHERE slug = NEW.slug;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS courses_slug_history ON courses;
+CREATE TRIGGER courses_slug_history AFTER UPDATE OF slug ON courses FOR EACH ROW EXECUTE FUNCTION record_course_slug_change();
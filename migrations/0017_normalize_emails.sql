@@ -0,0 +1,22 @@
+-- Les saisies sont normalisées (minuscules, sans espaces) avant comparaison : les adresses déjà enregistrées sont alignées.
+-- Doublons à la casse près : aucun compte n'est modifié automatiquement (inscriptions, achats, connexion), la migration
+-- échoue avec la liste des comptes concernés pour qu'un opérateur les fusionne ou les renomme avant de la relancer.
+DO $$
+DECLARE
+    conflicts TEXT;
+BEGIN
+    SELECT string_agg(format('%s (%s)', normalized, ids), E'\n' ORDER BY normalized) INTO conflicts
+    FROM (
+        SELECT lower(btrim(email)) AS normalized, string_agg(format('%s <%s>', id, email), ', ' ORDER BY last_login_at DESC NULLS LAST, created_at) AS ids
+        FROM users GROUP BY lower(btrim(email)) HAVING count(*) > 1
+    ) duplicates;
+    IF conflicts IS NOT NULL THEN
+        RAISE EXCEPTION 'users.email contains addresses that differ only by case or surrounding spaces:%', E'\n' || conflicts
+            USING HINT = 'Merge or rename these accounts (most recently active first), then run the migration again.';
+    END IF;
+END
+$$;
+
+UPDATE users SET email = lower(btrim(email)), updated_at = now() WHERE email <> lower(btrim(email));
+
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_lower_key ON users (lower(email));
@@ -0,0 +1,9 @@
+-- Suivi des jetons de connexion en attente de 2FA (par jti) : usage unique et nombre d'échecs borné
+CREATE TABLE IF NOT EXISTS mfa_token_attempts (
+    jti TEXT PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    failures INTEGER NOT NULL DEFAULT 0,
+    consumed_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS mfa_token_attempts_expires_at_idx ON mfa_token_attempts(expires_at);
@@ -0,0 +1,4 @@
+-- Profil public vide par défaut : seul le propriétaire choisit les champs visibles.
+-- Les comptes qui ont encore l'ancienne valeur par défaut n'ont jamais fait ce choix et redeviennent privés.
+ALTER TABLE users ALTER COLUMN public_profile_fields SET DEFAULT '{}';
+UPDATE users SET public_profile_fields = '{}' WHERE public_profile_fields = ARRAY['name', 'avatar_url', 'job_title', 'pcsoft_experience'];
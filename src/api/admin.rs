@@ -0,0 +1,108 @@
+use axum::{extract::{FromRef, Path, State}, routing::{delete, get, post}, Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+use crate::api::extract::{AdminUser, Client, ValidatedJson, ROLE_ADMIN};
+use crate::service::{api_key_service::{self, ApiKeyDto}, audit_service, settings_service};
+use crate::utils::{config::Config, error::AppError, jwt::create_impersonation_token, validation};
+
+#[derive(Clone, FromRef)]
+pub struct AdminState { pub pool: PgPool, pub cfg: Config }
+
+#[derive(Deserialize, Validate)]
+pub struct CreateApiKeyRequest {
+    #[validate(length(max = 100, message = "Name must be at most 100 characters"), custom(function = "validation::not_blank"))]
+    pub name: String,
+    #[validate(length(min = 1, message = "At least one scope is required"), custom(function = "known_scopes"))]
+    pub scopes: Vec<String>,
+    #[validate(range(min = 1, max = MAX_KEY_TTL_DAYS, message = "Expiry must be between 1 and 365 days"))]
+    pub expires_in_days: Option<i64>,
+}
+
+// La clé complète n'apparaît que dans cette réponse
+#[derive(Serialize)]
+pub struct CreatedApiKey { pub key: String, #[serde(flatten)] pub api_key: ApiKeyDto }
+
+// `url` absent ou null : plus d'image par défaut
+#[derive(Deserialize, Serialize, Validate)]
+pub struct DefaultAvatarSetting {
+    #[validate(length(max = 500, message = "Must be at most 500 characters"), custom(function = "validation::http_url"))]
+    pub url: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImpersonationResponse { pub token: String, pub expires_in: i64 }
+
+const DEFAULT_KEY_TTL_DAYS: i64 = 90;
+const MAX_KEY_TTL_DAYS: i64 = 365;
+const IMPERSONATION_TTL_MINUTES: i64 = 15;
+
+pub fn routes(pool: PgPool, cfg: Config) -> Router {
+    Router::new()
+        .route("/api-keys", get(list_api_keys).post(create_api_key))
+        .route("/api-keys/:id", delete(revoke_api_key))
+        .route("/impersonate/:user_id", post(impersonate))
+        .route("/settings/default-avatar", get(get_default_avatar).put(set_default_avatar))
+        .with_state(AdminState { pool, cfg })
+}
+
+fn known_scopes(scopes: &[String]) -> Result<(), ValidationError> {
+    if scopes.iter().all(|s| api_key_service::SCOPES.contains(&s.as_str())) { return Ok(()) }
+    Err(validation::error("unknown_scope", format!("Scopes must be chosen among: {}", api_key_service::SCOPES.join(", "))))
+}
+
+// Une clé d'API ne peut pas gérer les clés d'API
+fn require_interactive(admin: &AdminUser) -> Result<(), AppError> {
+    if admin.0.is_api_key() { Err(AppError::Forbidden) } else { Ok(()) }
+}
+
+async fn list_api_keys(admin: AdminUser, State(state): State<AdminState>) -> Result<Json<Vec<ApiKeyDto>>, AppError> {
+    require_interactive(&admin)?;
+    Ok(Json(api_key_service::list_keys(&state.pool, admin.0.id).await?))
+}
+
+async fn create_api_key(admin: AdminUser, Client(client): Client, State(state): State<AdminState>, ValidatedJson(req): ValidatedJson<CreateApiKeyRequest>) -> Result<Json<CreatedApiKey>, AppError> {
+    require_interactive(&admin)?;
+    let name = req.name.trim();
+    let ttl_days = req.expires_in_days.unwrap_or(DEFAULT_KEY_TTL_DAYS);
+
+    let (api_key, key) = api_key_service::create_key(&state.pool, admin.0.id, name, &req.scopes, ttl_days).await?;
+    audit_service::record(&state.pool, Some(admin.0.id), "admin.api_key_created", Some(admin.0.id), &client, json!({ "api_key_id": api_key.id, "prefix": api_key.prefix, "scopes": api_key.scopes })).await;
+    Ok(Json(CreatedApiKey { key, api_key }))
+}
+
+async fn revoke_api_key(admin: AdminUser, Client(client): Client, Path(id): Path<String>, State(state): State<AdminState>) -> Result<(), AppError> {
+    require_interactive(&admin)?;
+    let key_id = Uuid::parse_str(&id).map_err(|_| AppError::NotFound)?;
+    if !api_key_service::revoke_key(&state.pool, admin.0.id, key_id).await? { return Err(AppError::NotFound) }
+    audit_service::record(&state.pool, Some(admin.0.id), "admin.api_key_revoked", Some(admin.0.id), &client, json!({ "api_key_id": key_id })).await;
+    Ok(())
+}
+
+// Jeton court permettant au support de voir le site exactement comme l'utilisateur
+async fn impersonate(admin: AdminUser, Client(client): Client, Path(user_id): Path<String>, State(state): State<AdminState>) -> Result<Json<ImpersonationResponse>, AppError> {
+    require_interactive(&admin)?;
+    let user_id = Uuid::parse_str(&user_id).map_err(|_| AppError::NotFound)?;
+    let row = sqlx::query("SELECT COALESCE(role, 'user') AS role FROM users WHERE id = $1").bind(user_id).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::NotFound) };
+    let role: String = row.get("role");
+    // Un admin ne peut pas endosser l'identité d'un autre admin
+    if role == ROLE_ADMIN { return Err(AppError::Forbidden) }
+
+    let token = create_impersonation_token(&user_id.to_string(), &role, &admin.0.id.to_string(), &state.cfg.jwt_keys, IMPERSONATION_TTL_MINUTES)?;
+    audit_service::record(&state.pool, Some(admin.0.id), "admin.impersonation_started", Some(user_id), &client, json!({ "ttl_minutes": IMPERSONATION_TTL_MINUTES })).await;
+    Ok(Json(ImpersonationResponse { token, expires_in: IMPERSONATION_TTL_MINUTES * 60 }))
+}
+
+async fn get_default_avatar(_admin: AdminUser, State(state): State<AdminState>) -> Result<Json<DefaultAvatarSetting>, AppError> {
+    Ok(Json(DefaultAvatarSetting { url: settings_service::get_string(&state.pool, settings_service::DEFAULT_AVATAR_URL).await? }))
+}
+
+async fn set_default_avatar(admin: AdminUser, Client(client): Client, State(state): State<AdminState>, ValidatedJson(req): ValidatedJson<DefaultAvatarSetting>) -> Result<Json<DefaultAvatarSetting>, AppError> {
+    let url = req.url.filter(|u| !u.is_empty());
+    settings_service::set(&state.pool, settings_service::DEFAULT_AVATAR_URL, json!(url), admin.0.id).await?;
+    audit_service::record(&state.pool, Some(admin.0.id), "admin.settings_updated", None, &client, json!({ "key": settings_service::DEFAULT_AVATAR_URL, "value": url })).await;
+    Ok(Json(DefaultAvatarSetting { url }))
+}
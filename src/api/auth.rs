@@ -1,39 +1,312 @@
-use axum::{Router, routing::post, extract::State, Json};
+use axum::{Router, routing::{delete, get, post}, extract::{FromRef, Path, State}, middleware, Json};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
-use crate::utils::{config::Config, jwt::create_token, error::AppError};
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use validator::Validate;
+use crate::api::{extract::{AuthUser, Client, ROLE_ADMIN, ROLE_MFA_PENDING}, oauth, two_factor};
+use crate::service::{audit_service, auth_service::{self, IssuedTokens}, email_service, session_service::{self, ClientInfo, SessionDto}};
+use crate::api::extract::ValidatedJson;
+use crate::utils::{config::Config, jwt::create_token, error::AppError, password::{hash_password, verify_dummy, verify_password}, password_policy, rate_limit::{self, RateLimit}, token::{generate_token, hash_token}, validation::{self, normalize_email}};
 use sqlx::Row;
 
-#[derive(Clone)]
+#[derive(Clone, FromRef)]
 pub struct AuthState { pub pool: PgPool, pub cfg: Config }
 
 #[derive(Deserialize)]
 pub struct LoginRequest { pub email: String, pub password: String }
 
+#[derive(Deserialize, Validate)]
+pub struct RegisterRequest {
+    #[validate(length(max = 100, message = "Must be at most 100 characters"))]
+    pub name: Option<String>,
+    #[validate(custom(function = "validation::email"))]
+    pub email: String,
+    pub password: String,
+    #[serde(rename = "confirmPassword")]
+    pub confirm_password: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest { pub refresh_token: String }
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest { pub email: String }
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest { pub token: String, pub password: String }
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest { pub token: String }
+
+#[derive(Deserialize)]
+pub struct ResendVerificationRequest { pub email: String }
+
+#[derive(Deserialize)]
+pub struct MagicLinkRequest { pub email: String }
+
+#[derive(Deserialize)]
+pub struct ConsumeMagicLinkRequest { pub token: String }
+
 #[derive(Serialize)]
-pub struct LoginResponse { pub token: String }
+pub struct LoginResponse {
+    pub token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+// Mot de passe valide mais second facteur requis : le client rappelle /2fa/verify
+// (ou /2fa/setup puis /2fa/enable si l'enrôlement est obligatoire) avec `mfa_token`
+#[derive(Serialize)]
+pub struct MfaChallenge { pub mfa_required: bool, pub enrollment_required: bool, pub mfa_token: String }
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginOutcome { Tokens(LoginResponse), MfaRequired(MfaChallenge) }
+
+impl From<IssuedTokens> for LoginResponse {
+    fn from(t: IssuedTokens) -> Self { LoginResponse { token: t.access_token, refresh_token: Some(t.refresh_token) } }
+}
+
+const RESET_TOKEN_TTL_MINUTES: i64 = 30;
+const MFA_TOKEN_TTL_MINUTES: i64 = 5;
+const MAGIC_LINK_TTL_MINUTES: i64 = 15;
+// Par adresse : au plus un lien par minute et MAGIC_LINK_MAX_PER_HOUR liens par heure
+const MAGIC_LINK_MIN_INTERVAL_SECS: i64 = 60;
+const MAGIC_LINK_MAX_PER_HOUR: i64 = 5;
 
 pub fn routes(pool: PgPool, cfg: Config) -> Router {
-    Router::new().route("/login", post(login)).with_state(AuthState { pool, cfg })
+    // Quota réservé aux routes qui reçoivent un secret ou déclenchent un email ; refresh, sessions et OAuth n'y sont pas soumis
+    let limiter = RateLimit::per_minute(cfg.auth_rate_limit_per_minute, cfg.trusted_proxies.clone());
+    let limited = || middleware::from_fn_with_state(limiter.clone(), rate_limit::limit);
+    Router::new()
+        .route("/login", post(login).layer(limited()))
+        .route("/register", post(register).layer(limited()))
+        .route("/refresh", post(refresh))
+        .route("/forgot-password", post(forgot_password).layer(limited()))
+        .route("/reset-password", post(reset_password).layer(limited()))
+        .route("/verify-email", post(verify_email))
+        .route("/resend-verification", post(resend_verification).layer(limited()))
+        .route("/magic-link", post(request_magic_link).layer(limited()))
+        .route("/magic-link/consume", post(consume_magic_link).layer(limited()))
+        .route("/logout", post(logout))
+        .route("/logout-all", post(logout_all))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/:id", delete(revoke_session))
+        .nest("/2fa", two_factor::routes(limiter.clone()))
+        .nest("/oauth", oauth::routes())
+        .with_state(AuthState { pool, cfg })
 }
 
-async fn login(State(state): State<AuthState>, Json(req): Json<LoginRequest>) -> Result<Json<LoginResponse>, AppError> {
-    if let Some(admin) = &state.cfg.admin_auth {
-        let parts: Vec<&str> = admin.split(":").collect();
-        if parts.len() == 2 && req.email == parts[0] && req.password == parts[1] {
-            let token = create_token("admin", "admin", &state.cfg.jwt_secret, 60)?;
-            return Ok(Json(LoginResponse { token }));
-        }
-    }
-    let row = sqlx::query("SELECT id, password_hash, role FROM users WHERE email = $1").bind(&req.email).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
-    let Some(user) = row else { return Err(AppError::Unauthorized) };
+async fn login(State(state): State<AuthState>, Client(client): Client, Json(req): Json<LoginRequest>) -> Result<Json<LoginOutcome>, AppError> {
+    let email = normalize_email(&req.email);
+    let row = sqlx::query("SELECT id, password_hash, role, totp_enabled_at IS NOT NULL AS totp_enabled FROM users WHERE email = $1").bind(&email).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
+    let Some(user) = row else {
+        verify_dummy(&req.password);
+        return Err(AppError::Unauthorized);
+    };
     let password_hash: String = user.get("password_hash");
-    let parsed = PasswordHash::new(&password_hash).map_err(|_| AppError::Internal)?;
-    Argon2::default().verify_password(req.password.as_bytes(), &parsed).map_err(|_| AppError::Unauthorized)?;
     let id: uuid::Uuid = user.get("id");
     let role: Option<String> = user.get("role");
-    let token = create_token(&id.to_string(), role.as_deref().unwrap_or("user"), &state.cfg.jwt_secret, 60)?;
-    Ok(Json(LoginResponse { token }))
+    let role = role.as_deref().unwrap_or("user");
+    if let Some(remaining_secs) = auth_service::lockout_remaining(&state.pool, id).await? {
+        // Même réponse (et même coût) qu'une adresse inconnue : le verrouillage ne révèle pas l'existence du compte
+        verify_dummy(&req.password);
+        tracing::warn!(user_id = %id, remaining_secs, "login attempt on locked account");
+        return Err(AppError::Unauthorized);
+    }
+    if let Err(e) = verify_password(&req.password, &password_hash) {
+        auth_service::record_failed_login(&state.pool, &state.cfg, id).await?;
+        if role == ROLE_ADMIN {
+            audit_service::record(&state.pool, Some(id), "admin.login_failed", Some(id), &client, serde_json::json!({})).await;
+        }
+        return Err(e);
+    }
+    let totp_enabled: bool = user.get("totp_enabled");
+    Ok(Json(complete_login(&state, id, role, totp_enabled, &client).await?))
+}
+
+// Premier facteur validé (mot de passe, fournisseur externe...) : émet les jetons ou exige le second facteur
+pub async fn complete_login(state: &AuthState, id: uuid::Uuid, role: &str, totp_enabled: bool, client: &ClientInfo) -> Result<LoginOutcome, AppError> {
+    if totp_enabled || (state.cfg.require_admin_2fa && role == ROLE_ADMIN) {
+        let mfa_token = create_token(&id.to_string(), ROLE_MFA_PENDING, None, &state.cfg.jwt_keys, MFA_TOKEN_TTL_MINUTES)?;
+        return Ok(LoginOutcome::MfaRequired(MfaChallenge { mfa_required: true, enrollment_required: !totp_enabled, mfa_token }));
+    }
+    let tokens = auth_service::issue_tokens(&state.pool, &state.cfg, id, role, client).await?;
+    Ok(LoginOutcome::Tokens(tokens.into()))
 }
 
+async fn register(State(state): State<AuthState>, Client(client): Client, ValidatedJson(req): ValidatedJson<RegisterRequest>) -> Result<Json<LoginResponse>, AppError> {
+    let email = normalize_email(&req.email);
+    if let Some(confirm) = &req.confirm_password {
+        if confirm != &req.password { return Err(AppError::field("confirmPassword", "Passwords do not match")) }
+    }
+    let full_name = req.name.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    password_policy::check(&state.cfg, &req.password, &[&email, full_name.unwrap_or_default()]).await?;
+    let password_hash = hash_password(&req.password)?;
+
+    let row = sqlx::query("INSERT INTO users (email, password_hash, role, full_name) VALUES ($1, $2, 'user', $3) RETURNING id")
+        .bind(&email)
+        .bind(&password_hash)
+        .bind(full_name)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| match e.as_database_error().and_then(|d| d.code()) {
+            // Violation de la contrainte UNIQUE sur users.email
+            Some(code) if code == "23505" => AppError::Conflict,
+            _ => AppError::Internal,
+        })?;
+    let id: uuid::Uuid = row.get("id");
+    auth_service::send_email_verification(&state.pool, &state.cfg, id, &email).await?;
+    let tokens = auth_service::issue_tokens(&state.pool, &state.cfg, id, "user", &client).await?;
+    Ok(Json(tokens.into()))
+}
+
+async fn refresh(State(state): State<AuthState>, Client(client): Client, Json(req): Json<RefreshRequest>) -> Result<Json<LoginResponse>, AppError> {
+    let tokens = auth_service::rotate_refresh_token(&state.pool, &state.cfg, &req.refresh_token, &client).await?;
+    Ok(Json(tokens.into()))
+}
+
+async fn forgot_password(State(state): State<AuthState>, Json(req): Json<ForgotPasswordRequest>) -> Result<(), AppError> {
+    let email = normalize_email(&req.email);
+    let row = sqlx::query("SELECT id FROM users WHERE email = $1").bind(&email).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
+    // Réponse identique que le compte existe ou non, pour ne pas divulguer les adresses inscrites
+    let Some(user) = row else { return Ok(()) };
+    let user_id: uuid::Uuid = user.get("id");
+
+    let token = generate_token();
+    sqlx::query("INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, now() + make_interval(mins => $3))")
+        .bind(user_id)
+        .bind(hash_token(&token))
+        .bind(RESET_TOKEN_TTL_MINUTES as i32)
+        .execute(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+    let link = format!("{}/reset-password?token={}", state.cfg.frontend_url, token);
+    let body = format!("Bonjour,\n\nPour choisir un nouveau mot de passe, ouvrez ce lien (valable {} minutes) :\n{}\n\nSi vous n'êtes pas à l'origine de cette demande, ignorez cet email.", RESET_TOKEN_TTL_MINUTES, link);
+    email_service::spawn_send(state.cfg.smtp_config.clone(), email, "Réinitialisation de votre mot de passe".to_string(), body);
+    Ok(())
+}
+
+async fn reset_password(State(state): State<AuthState>, Json(req): Json<ResetPasswordRequest>) -> Result<(), AppError> {
+    let mut tx = state.pool.begin().await.map_err(|_| AppError::Internal)?;
+    let row = sqlx::query("SELECT t.id, t.user_id, u.email, u.full_name FROM password_reset_tokens t JOIN users u ON u.id = t.user_id WHERE t.token_hash = $1 AND t.used_at IS NULL AND t.expires_at > now() FOR UPDATE OF t")
+        .bind(hash_token(&req.token))
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::BadRequest) };
+    let user_id: uuid::Uuid = row.get("user_id");
+    let (email, full_name): (String, Option<String>) = (row.get("email"), row.get("full_name"));
+    password_policy::check(&state.cfg, &req.password, &[&email, full_name.as_deref().unwrap_or_default()]).await?;
+
+    let password_hash = hash_password(&req.password)?;
+    sqlx::query("UPDATE users SET password_hash = $1, failed_login_attempts = 0, locked_until = NULL, updated_at = now() WHERE id = $2").bind(&password_hash).bind(user_id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    // Le jeton utilisé et ceux encore en attente deviennent inutilisables
+    sqlx::query("UPDATE password_reset_tokens SET used_at = now() WHERE user_id = $1 AND used_at IS NULL").bind(user_id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    session_service::revoke_all_sessions(&mut *tx, user_id, None).await?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+    Ok(())
+}
+
+async fn verify_email(State(state): State<AuthState>, Json(req): Json<VerifyEmailRequest>) -> Result<(), AppError> {
+    let mut tx = state.pool.begin().await.map_err(|_| AppError::Internal)?;
+    let row = sqlx::query("UPDATE email_verification_tokens SET used_at = now() WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now() RETURNING user_id, email")
+        .bind(hash_token(&req.token))
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::BadRequest) };
+    let user_id: uuid::Uuid = row.get("user_id");
+    let email: String = row.get("email");
+    // Le lien valide l'adresse qu'il porte : adresse d'inscription, ou nouvelle adresse demandée, qui remplace alors l'actuelle
+    sqlx::query("UPDATE users SET email = $2, email_verified_at = now(), updated_at = now() WHERE id = $1")
+        .bind(user_id)
+        .bind(&email)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e.as_database_error().and_then(|d| d.code()) {
+            Some(code) if code == "23505" => AppError::Conflict,
+            _ => AppError::Internal,
+        })?;
+    // Les autres liens en attente (ancienne adresse, demande précédente) ne doivent plus changer l'adresse
+    sqlx::query("UPDATE email_verification_tokens SET used_at = now() WHERE user_id = $1 AND used_at IS NULL").bind(user_id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+    Ok(())
+}
+
+async fn resend_verification(State(state): State<AuthState>, Json(req): Json<ResendVerificationRequest>) -> Result<(), AppError> {
+    let email = normalize_email(&req.email);
+    let row = sqlx::query("SELECT id FROM users WHERE email = $1 AND email_verified_at IS NULL").bind(&email).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
+    let Some(user) = row else { return Ok(()) };
+    auth_service::send_email_verification(&state.pool, &state.cfg, user.get("id"), &email).await
+}
+
+async fn logout(user: AuthUser, State(state): State<AuthState>) -> Result<(), AppError> {
+    let Some(session_id) = user.session_id else { return Ok(()) };
+    session_service::revoke_session(&state.pool, user.id, session_id).await?;
+    Ok(())
+}
+
+async fn logout_all(user: AuthUser, State(state): State<AuthState>) -> Result<(), AppError> {
+    session_service::revoke_all_sessions(&state.pool, user.id, None).await
+}
+
+async fn list_sessions(user: AuthUser, State(state): State<AuthState>) -> Result<Json<Vec<SessionDto>>, AppError> {
+    Ok(Json(session_service::list_sessions(&state.pool, user.id, user.session_id).await?))
+}
+
+async fn revoke_session(user: AuthUser, Path(id): Path<uuid::Uuid>, State(state): State<AuthState>) -> Result<(), AppError> {
+    if !session_service::revoke_session(&state.pool, user.id, id).await? { return Err(AppError::NotFound) }
+    Ok(())
+}
+
+async fn request_magic_link(State(state): State<AuthState>, Json(req): Json<MagicLinkRequest>) -> Result<(), AppError> {
+    let email = normalize_email(&req.email);
+    let row = sqlx::query("SELECT u.id, count(m.id) FILTER (WHERE m.created_at > now() - interval '1 hour') AS last_hour, max(m.created_at) > now() - make_interval(secs => $2) AS too_soon FROM users u LEFT JOIN magic_link_tokens m ON m.user_id = u.id WHERE u.email = $1 GROUP BY u.id")
+        .bind(&email)
+        .bind(MAGIC_LINK_MIN_INTERVAL_SECS as f64)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    // Même réponse pour une adresse inconnue ou throttlée, pour ne rien divulguer
+    let Some(user) = row else { return Ok(()) };
+    let user_id: uuid::Uuid = user.get("id");
+    let last_hour: i64 = user.get("last_hour");
+    let too_soon: Option<bool> = user.get("too_soon");
+    if too_soon == Some(true) || last_hour >= MAGIC_LINK_MAX_PER_HOUR {
+        tracing::info!(%user_id, "magic link request throttled");
+        return Ok(());
+    }
+
+    let token = generate_token();
+    sqlx::query("INSERT INTO magic_link_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, now() + make_interval(mins => $3))")
+        .bind(user_id)
+        .bind(hash_token(&token))
+        .bind(MAGIC_LINK_TTL_MINUTES as i32)
+        .execute(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+    let link = format!("{}/magic-link?token={}", state.cfg.frontend_url, token);
+    let body = format!("Bonjour,\n\nCliquez sur ce lien pour vous connecter à WindevExpert (valable {} minutes, utilisable une seule fois) :\n{}\n\nSi vous n'êtes pas à l'origine de cette demande, ignorez cet email.", MAGIC_LINK_TTL_MINUTES, link);
+    email_service::spawn_send(state.cfg.smtp_config.clone(), email, "Votre lien de connexion".to_string(), body);
+    Ok(())
+}
+
+async fn consume_magic_link(State(state): State<AuthState>, Client(client): Client, Json(req): Json<ConsumeMagicLinkRequest>) -> Result<Json<LoginOutcome>, AppError> {
+    let row = sqlx::query("UPDATE magic_link_tokens SET used_at = now() WHERE token_hash = $1 AND used_at IS NULL AND expires_at > now() RETURNING user_id")
+        .bind(hash_token(&req.token))
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::Unauthorized) };
+    let user_id: uuid::Uuid = row.get("user_id");
+    // Ouvrir le lien prouve la maîtrise de l'adresse
+    let user = sqlx::query("UPDATE users SET email_verified_at = COALESCE(email_verified_at, now()) WHERE id = $1 RETURNING role, totp_enabled_at IS NOT NULL AS totp_enabled")
+        .bind(user_id)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let role: Option<String> = user.get("role");
+    Ok(Json(complete_login(&state, user_id, role.as_deref().unwrap_or("user"), user.get("totp_enabled"), &client).await?))
+}
@@ -1,11 +1,15 @@
-use axum::{extract::{Path, State, Query}, routing::{get, post}, Json, Router};
-use axum::http::HeaderMap;
+use std::collections::HashMap;
+use axum::{extract::{FromRef, Path, State, Query}, routing::{get, post}, Json, Router};
+use chrono::{DateTime, Utc};
 use serde::Serialize;
+use serde_json::Value;
 use sqlx::PgPool;
 use sqlx::Row;
+use uuid::Uuid;
+use crate::api::extract::{AuthUser, MaybeAuthUser};
 use crate::utils::{config::Config, error::AppError};
 
-#[derive(Clone)]
+#[derive(Clone, FromRef)]
 pub struct CoursesState { pub pool: PgPool, pub cfg: Config }
 
 #[derive(Serialize)]
@@ -14,6 +18,7 @@ pub struct PurchaseResponse { pub stripe_session_url: String }
 #[derive(Serialize)]
 pub struct CourseDto {
     pub id: String,
+    pub slug: String,
     pub title: String,
     pub subtitle: Option<String>,
     pub description: String,
@@ -34,32 +39,79 @@ pub struct CourseDto {
     pub instructor: Option<serde_json::Value>,
 }
 
+// Les clés de stockage (vidéos, fichiers) ne sont jamais exposées : l'accès passe par des URL signées
+#[derive(Serialize)]
+pub struct ResourceDto { pub id: String, pub title: String, #[serde(rename = "type")] pub kind: String, pub file_size: Option<i64> }
+
+#[derive(Serialize)]
+pub struct LessonDto {
+    pub id: String,
+    pub module_id: String,
+    pub title: String,
+    pub description: Option<String>,
+    #[serde(rename = "order_index")]
+    pub position: i32,
+    pub duration_seconds: i64,
+    pub is_free_preview: bool,
+    pub has_video: bool,
+    pub resources: Vec<ResourceDto>,
+}
+
+// Un module du cours : un "chapitre" pour le frontend (Chapter, CourseWithChapters)
+#[derive(Serialize)]
+pub struct ModuleDto { pub id: String, pub course_id: String, pub title: String, pub description: Option<String>, #[serde(rename = "order_index")] pub position: i32, pub duration_seconds: i64, pub lessons: Vec<LessonDto> }
+
+#[derive(Serialize)]
+pub struct EnrollmentStatus { pub enrolled: bool, pub enrolled_at: Option<DateTime<Utc>>, pub completed_lessons: i64 }
+
+// Cours atteint par un ancien slug : le client doit mettre à jour ses liens (équivalent d'un 301)
+#[derive(Serialize)]
+pub struct SlugRedirect { pub status: u16, pub slug: String, pub location: String }
+
+#[derive(Serialize)]
+pub struct CourseDetailDto {
+    #[serde(flatten)]
+    pub course: CourseDto,
+    pub total_duration_seconds: i64,
+    pub lessons_count: usize,
+    pub chapters: Vec<ModuleDto>,
+    pub resources: Vec<ResourceDto>,
+    // Absent pour un visiteur anonyme
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enrollment: Option<EnrollmentStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<SlugRedirect>,
+}
+
+pub struct ResolvedCourse { pub id: Uuid, pub slug: String, pub moved: bool }
+
 #[derive(Debug, Default, Clone, serde::Deserialize)]
 pub struct ListParams { pub version: Option<String>, pub level: Option<String> }
 
 pub fn routes(pool: PgPool, cfg: Config) -> Router {
     Router::new()
         .route("/", get(list_courses))
+        .route("/by-slug/:slug", get(get_course))
+        .route("/:id", get(get_course))
         .route("/:id/purchase", post(purchase))
         .with_state(CoursesState { pool, cfg })
 }
 
-async fn purchase(Path(id): Path<String>, State(state): State<CoursesState>, headers: HeaderMap) -> Result<Json<PurchaseResponse>, AppError> {
+async fn purchase(user: AuthUser, Path(key): Path<String>, State(state): State<CoursesState>) -> Result<Json<PurchaseResponse>, AppError> {
+    user.deny_impersonation()?;
+    let id = resolve_course(&state.pool, &key).await?.id;
     // Récupérer le cours pour déterminer le prix et le nom
     let row = sqlx::query(
         "SELECT title, COALESCE(price::float8, 0) AS price FROM courses WHERE id = $1"
-    ).bind(&id).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
+    ).bind(id).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
 
     let Some(row) = row else { return Err(AppError::NotFound) };
     let title: String = row.get::<String, _>("title");
     let price_f: f64 = row.get::<f64, _>("price");
     let amount_cents: i64 = (price_f.max(0.0) * 100.0).round() as i64;
 
-    // Extraire l'utilisateur depuis le token JWT
-    let auth = headers.get(axum::http::header::AUTHORIZATION).and_then(|v| v.to_str().ok()).unwrap_or("");
-    let token = auth.strip_prefix("Bearer ").unwrap_or("");
-    let claims = crate::utils::jwt::validate_token(token, &state.cfg.jwt_secret).map_err(|_| AppError::Unauthorized)?;
-    let user_id = claims.claims.sub;
+    crate::service::auth_service::ensure_email_verified(&state.pool, &state.cfg, user.id).await?;
+    let user_id = user.id.to_string();
 
     // Clé secrète Stripe
     let secret = state.cfg.stripe_keys.clone().ok_or(AppError::Internal)?;
@@ -80,7 +132,8 @@ async fn purchase(Path(id): Path<String>, State(state): State<CoursesState>, hea
     // Metadata pour lier session à user/course
     form.push(("client_reference_id", user_id.clone()));
     form.push(("metadata[user_id]", user_id));
-    form.push(("metadata[course_id]", id.clone()));
+    // Toujours l'UUID : le webhook crée l'inscription à partir de cette valeur
+    form.push(("metadata[course_id]", id.to_string()));
 
     let resp = client
         .post("https://api.stripe.com/v1/checkout/sessions")
@@ -103,20 +156,21 @@ async fn purchase(Path(id): Path<String>, State(state): State<CoursesState>, hea
 async fn list_courses(State(state): State<CoursesState>, Query(params): Query<ListParams>) -> Result<Json<Vec<CourseDto>>, AppError> {
     let mut courses: Vec<CourseDto> = Vec::new();
     let q = if let (Some(ver), Some(level)) = (params.version.clone(), params.level.clone()) {
-        sqlx::query("SELECT id, title, subtitle, COALESCE(description_long, '') AS description, thumbnail_url, intro_video_url, COALESCE(price::float8, 0) AS price, level, rating_average, rating_count, students_count FROM courses WHERE (compatibility_versions ? $1) AND level::text = $2")
+        sqlx::query("SELECT id, slug, title, subtitle, COALESCE(description_long, '') AS description, thumbnail_url, intro_video_url, COALESCE(price::float8, 0) AS price, level::text AS level, rating_average, rating_count::int8 AS rating_count, students_count::int8 AS students_count FROM courses WHERE (compatibility_versions ? $1) AND level::text = $2")
             .bind(ver)
             .bind(level)
     } else if let Some(ver) = params.version.clone() {
-        sqlx::query("SELECT id, title, subtitle, COALESCE(description_long, '') AS description, thumbnail_url, intro_video_url, COALESCE(price::float8, 0) AS price, level, rating_average, rating_count, students_count FROM courses WHERE (compatibility_versions ? $1)")
+        sqlx::query("SELECT id, slug, title, subtitle, COALESCE(description_long, '') AS description, thumbnail_url, intro_video_url, COALESCE(price::float8, 0) AS price, level::text AS level, rating_average, rating_count::int8 AS rating_count, students_count::int8 AS students_count FROM courses WHERE (compatibility_versions ? $1)")
             .bind(ver)
     } else {
-        sqlx::query("SELECT id, title, subtitle, COALESCE(description_long, '') AS description, thumbnail_url, intro_video_url, COALESCE(price::float8, 0) AS price, level, rating_average, rating_count, students_count FROM courses")
+        sqlx::query("SELECT id, slug, title, subtitle, COALESCE(description_long, '') AS description, thumbnail_url, intro_video_url, COALESCE(price::float8, 0) AS price, level::text AS level, rating_average, rating_count::int8 AS rating_count, students_count::int8 AS students_count FROM courses")
     };
 
     match q.fetch_all(&state.pool).await {
         Ok(rows) => {
             for r in rows {
                 let id: uuid::Uuid = r.get("id");
+                let slug: String = r.get("slug");
                 let title: String = r.get("title");
                 let subtitle: Option<String> = r.try_get("subtitle").ok();
                 let description: String = r.get("description");
@@ -131,6 +185,7 @@ async fn list_courses(State(state): State<CoursesState>, Query(params): Query<Li
                 let version = params.version.clone().unwrap_or_else(|| "WD25".to_string());
                 let dto = CourseDto {
                     id: id.to_string(),
+                    slug,
                     title,
                     subtitle,
                     description,
@@ -160,3 +215,134 @@ async fn list_courses(State(state): State<CoursesState>, Query(params): Query<Li
     }
 }
 
+
+// Tableau JSONB de chaînes ; toute autre forme est ignorée
+fn json_strings(value: Option<Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(items)) => items.into_iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Les routes :id acceptent l'UUID, le slug actuel ou un ancien slug
+pub async fn resolve_course(pool: &PgPool, key: &str) -> Result<ResolvedCourse, AppError> {
+    let query = match Uuid::parse_str(key) {
+        Ok(id) => sqlx::query("SELECT id, slug, false AS moved FROM courses WHERE id = $1").bind(id),
+        Err(_) => sqlx::query("SELECT id, slug, false AS moved FROM courses WHERE slug = $1 UNION ALL SELECT c.id, c.slug, true FROM course_slug_history h JOIN courses c ON c.id = h.course_id WHERE h.slug = $1 ORDER BY moved LIMIT 1").bind(key),
+    };
+    let row = query.fetch_optional(pool).await.map_err(|_| AppError::Internal)?;
+    let Some(r) = row else { return Err(AppError::NotFound) };
+    Ok(ResolvedCourse { id: r.get("id"), slug: r.get("slug"), moved: r.get("moved") })
+}
+
+// GET /:id et GET /by-slug/:slug
+async fn get_course(MaybeAuthUser(user): MaybeAuthUser, Path(key): Path<String>, State(state): State<CoursesState>) -> Result<Json<CourseDetailDto>, AppError> {
+    let resolved = resolve_course(&state.pool, &key).await?;
+    let id = resolved.id;
+    let row = sqlx::query("SELECT id, title, subtitle, slug, is_published, COALESCE(description_long, description_short, '') AS description, thumbnail_url, intro_video_url, COALESCE(price::float8, 0) AS price, level::text AS level, compatibility_versions->>0 AS version, prerequisites, learning_objectives, rating_average, rating_count::int8 AS rating_count, students_count::int8 AS students_count FROM courses WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(r) = row else { return Err(AppError::NotFound) };
+    // Un cours non publié n'existe que pour les admins
+    let is_admin = user.as_ref().is_some_and(AuthUser::is_admin);
+    if !r.get::<bool, _>("is_published") && !is_admin { return Err(AppError::NotFound) }
+
+    let lessons = sqlx::query("SELECT l.id, l.module_id, l.title, l.description, l.position, COALESCE(l.duration_seconds, 0)::int8 AS duration_seconds, l.is_free_preview, l.video_s3_key IS NOT NULL AS has_video FROM lessons l JOIN modules m ON m.id = l.module_id WHERE m.course_id = $1 ORDER BY l.position, l.id")
+        .bind(id)
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let resources = sqlx::query("SELECT r.id, r.lesson_id, r.title, r.type::text AS kind, r.file_size FROM resources r LEFT JOIN lessons l ON l.id = r.lesson_id LEFT JOIN modules m ON m.id = l.module_id WHERE r.course_id = $1 OR m.course_id = $1 ORDER BY r.title, r.id")
+        .bind(id)
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let modules = sqlx::query("SELECT id, title, description, position FROM modules WHERE course_id = $1 ORDER BY position, id")
+        .bind(id)
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+    let mut course_resources = Vec::new();
+    let mut lesson_resources: HashMap<Uuid, Vec<ResourceDto>> = HashMap::new();
+    for r in resources {
+        let dto = ResourceDto { id: r.get::<Uuid, _>("id").to_string(), title: r.get("title"), kind: r.get("kind"), file_size: r.get("file_size") };
+        match r.get::<Option<Uuid>, _>("lesson_id") {
+            Some(lesson_id) => lesson_resources.entry(lesson_id).or_default().push(dto),
+            None => course_resources.push(dto),
+        }
+    }
+
+    let lessons_count = lessons.len();
+    let mut module_lessons: HashMap<Uuid, Vec<LessonDto>> = HashMap::new();
+    for l in lessons {
+        let (lesson_id, module_id): (Uuid, Uuid) = (l.get("id"), l.get("module_id"));
+        module_lessons.entry(module_id).or_default().push(LessonDto {
+            id: lesson_id.to_string(),
+            module_id: module_id.to_string(),
+            title: l.get("title"),
+            description: l.get("description"),
+            position: l.get("position"),
+            duration_seconds: l.get("duration_seconds"),
+            is_free_preview: l.get("is_free_preview"),
+            has_video: l.get("has_video"),
+            resources: lesson_resources.remove(&lesson_id).unwrap_or_default(),
+        });
+    }
+    let modules: Vec<ModuleDto> = modules.into_iter().map(|m| {
+        let module_id: Uuid = m.get("id");
+        let lessons = module_lessons.remove(&module_id).unwrap_or_default();
+        ModuleDto { id: module_id.to_string(), course_id: id.to_string(), title: m.get("title"), description: m.get("description"), position: m.get("position"), duration_seconds: lessons.iter().map(|l| l.duration_seconds).sum(), lessons }
+    }).collect();
+
+    let enrollment = match &user {
+        Some(user) => {
+            let e = sqlx::query("SELECT e.created_at, (SELECT count(*) FROM lesson_progress p JOIN lessons l ON l.id = p.lesson_id JOIN modules m ON m.id = l.module_id WHERE p.user_id = $1 AND m.course_id = $2) AS completed FROM enrollments e WHERE e.user_id = $1 AND e.course_id = $2")
+                .bind(user.id)
+                .bind(id)
+                .fetch_optional(&state.pool)
+                .await
+                .map_err(|_| AppError::Internal)?;
+            Some(match e {
+                Some(e) => EnrollmentStatus { enrolled: true, enrolled_at: e.get("created_at"), completed_lessons: e.get("completed") },
+                None => EnrollmentStatus { enrolled: false, enrolled_at: None, completed_lessons: 0 },
+            })
+        }
+        None => None,
+    };
+
+    let objectives = json_strings(r.get("learning_objectives"));
+    let course = CourseDto {
+        id: id.to_string(),
+        slug: r.get("slug"),
+        title: r.get("title"),
+        subtitle: r.get("subtitle"),
+        description: r.get("description"),
+        thumbnail_url: r.get("thumbnail_url"),
+        intro_video_url: r.get("intro_video_url"),
+        price: r.get("price"),
+        version: r.get::<Option<String>, _>("version").unwrap_or_else(|| "WD25".to_string()),
+        level: r.get("level"),
+        category: "windev".to_string(),
+        prerequisites: json_strings(r.get("prerequisites")),
+        learning_objectives: Some(objectives.clone()),
+        objectives,
+        is_featured: false,
+        rating_average: r.get("rating_average"),
+        rating_count: r.get("rating_count"),
+        students_count: r.get("students_count"),
+        created_at: Utc::now().to_rfc3339(),
+        instructor: None,
+    };
+    Ok(Json(CourseDetailDto {
+        course,
+        total_duration_seconds: modules.iter().map(|m| m.duration_seconds).sum(),
+        lessons_count,
+        chapters: modules,
+        resources: course_resources,
+        enrollment,
+        redirect: resolved.moved.then(|| SlugRedirect { status: 301, location: format!("/api/courses/by-slug/{}", urlencoding::encode(&resolved.slug)), slug: resolved.slug }),
+    }))
+}
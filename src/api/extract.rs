@@ -0,0 +1,162 @@
+use std::net::SocketAddr;
+use axum::{async_trait, extract::{ConnectInfo, FromRef, FromRequest, FromRequestParts, OriginalUri, Request}, http::{header, request::Parts, Method}, Json};
+use serde::de::DeserializeOwned;
+use validator::Validate;
+use sqlx::PgPool;
+use uuid::Uuid;
+use serde_json::json;
+use crate::service::{api_key_service, audit_service, session_service::{self, ClientInfo}};
+use crate::utils::{config::Config, error::AppError, jwt::validate_token, rate_limit::client_ip};
+
+pub const ROLE_ADMIN: &str = "admin";
+// Rôle porté par le jeton intermédiaire émis entre le mot de passe et le second facteur
+pub const ROLE_MFA_PENDING: &str = "mfa_pending";
+
+// Utilisateur authentifié par un access token `Authorization: Bearer <jwt>` ou une clé d'API
+// `Bearer wdx_...` ; `scopes` n'est renseigné que pour une clé d'API, `impersonator` que pour
+// un jeton d'usurpation émis par un admin
+#[derive(Debug, Clone)]
+pub struct AuthUser { pub id: Uuid, pub role: String, pub session_id: Option<Uuid>, pub scopes: Option<Vec<String>>, pub impersonator: Option<Uuid> }
+
+impl AuthUser {
+    pub fn is_api_key(&self) -> bool { self.scopes.is_some() }
+
+    // Paiements et identifiants restent hors de portée d'un admin agissant au nom d'un utilisateur
+    pub fn deny_impersonation(&self) -> Result<(), AppError> {
+        if self.impersonator.is_some() { Err(AppError::Forbidden) } else { Ok(()) }
+    }
+
+    pub fn is_admin(&self) -> bool { self.role == ROLE_ADMIN }
+}
+
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts.headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("Bearer ")).map(str::trim)
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser where S: Send + Sync, Config: FromRef<S>, PgPool: FromRef<S> {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let cfg = Config::from_ref(state);
+        let token = bearer_token(parts).ok_or(AppError::Unauthorized)?;
+        if token.starts_with(api_key_service::KEY_PREFIX) {
+            let token = token.to_string();
+            let Client(client) = Client::from_request_parts(parts, state).await?;
+            let principal = api_key_service::authenticate(&PgPool::from_ref(state), &token, &client).await?.ok_or(AppError::Unauthorized)?;
+            if !principal.scopes.contains(&required_scope(parts).ok_or(AppError::Forbidden)?) { return Err(AppError::Forbidden) }
+            return Ok(AuthUser { id: principal.user_id, role: principal.role, session_id: None, scopes: Some(principal.scopes), impersonator: None });
+        }
+        let data = validate_token(token, &cfg.jwt_keys)?;
+        if data.claims.role == ROLE_MFA_PENDING { return Err(AppError::Unauthorized) }
+        let id = Uuid::parse_str(&data.claims.sub).map_err(|_| AppError::Unauthorized)?;
+        let session_id = data.claims.sid.as_deref().map(Uuid::parse_str).transpose().map_err(|_| AppError::Unauthorized)?;
+        // Un access token encore valide est refusé dès que sa session est révoquée
+        if let Some(sid) = session_id {
+            if !session_service::is_session_active(&PgPool::from_ref(state), sid).await? { return Err(AppError::Unauthorized) }
+        }
+        let impersonator = data.claims.act.as_ref().map(|a| Uuid::parse_str(&a.sub)).transpose().map_err(|_| AppError::Unauthorized)?;
+        // Toute écriture faite sous usurpation est journalisée au nom de l'admin
+        if let Some(admin_id) = impersonator {
+            if !matches!(parts.method, Method::GET | Method::HEAD | Method::OPTIONS) {
+                let Client(client) = Client::from_request_parts(parts, state).await?;
+                let path = parts.extensions.get::<OriginalUri>().map(|u| u.0.path()).unwrap_or(parts.uri.path());
+                audit_service::record(&PgPool::from_ref(state), Some(admin_id), "impersonation.write", Some(id), &client, json!({ "method": parts.method.as_str(), "path": path })).await;
+            }
+        }
+        Ok(AuthUser { id, role: data.claims.role, session_id, scopes: None, impersonator })
+    }
+}
+
+// Portée exigée d'une clé d'API : zone (premier segment après /api/) et type d'accès
+fn required_scope(parts: &Parts) -> Option<String> {
+    let path = parts.extensions.get::<OriginalUri>().map(|u| u.0.path()).unwrap_or(parts.uri.path());
+    let area = match path.strip_prefix("/api/")?.split('/').next()? {
+        "user" => "profile",
+        "lessons" => "courses",
+        area => area,
+    };
+    let access = if parts.method == Method::GET || parts.method == Method::HEAD { "read" } else { "write" };
+    Some(format!("{area}:{access}"))
+}
+
+// Pour les routes publiques : un token absent ou invalide donne un visiteur anonyme
+#[derive(Debug, Clone)]
+pub struct MaybeAuthUser(pub Option<AuthUser>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for MaybeAuthUser where S: Send + Sync, Config: FromRef<S>, PgPool: FromRef<S> {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(MaybeAuthUser(AuthUser::from_request_parts(parts, state).await.ok()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthUser);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminUser where S: Send + Sync, Config: FromRef<S>, PgPool: FromRef<S> {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        if !user.is_admin() { return Err(AppError::Forbidden) }
+        Ok(AdminUser(user))
+    }
+}
+
+// Titulaire d'un compte en cours de configuration ou de validation 2FA : accepte un access
+// token normal ou le jeton intermédiaire "mfa_pending" (enrôlement obligatoire des admins)
+#[derive(Debug, Clone)]
+pub struct MfaSubject { pub id: Uuid, pub pending: bool }
+
+#[async_trait]
+impl<S> FromRequestParts<S> for MfaSubject where S: Send + Sync, Config: FromRef<S>, PgPool: FromRef<S> {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let cfg = Config::from_ref(state);
+        let token = bearer_token(parts).ok_or(AppError::Unauthorized)?;
+        let data = validate_token(token, &cfg.jwt_keys)?;
+        if data.claims.role == ROLE_MFA_PENDING {
+            let id = Uuid::parse_str(&data.claims.sub).map_err(|_| AppError::Unauthorized)?;
+            return Ok(MfaSubject { id, pending: true });
+        }
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        // Le second facteur fait partie des identifiants du compte
+        user.deny_impersonation()?;
+        Ok(MfaSubject { id: user.id, pending: false })
+    }
+}
+
+// IP et User-Agent de l'appelant, enregistrés avec les sessions
+pub struct Client(pub ClientInfo);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Client where S: Send + Sync, Config: FromRef<S> {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let cfg = Config::from_ref(state);
+        let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|c| c.0.ip());
+        let ip = client_ip(peer, &parts.headers, &cfg.trusted_proxies);
+        let user_agent = parts.headers.get(header::USER_AGENT).and_then(|v| v.to_str().ok()).map(|ua| ua.chars().take(512).collect());
+        Ok(Client(ClientInfo { ip, user_agent }))
+    }
+}
+
+// Corps JSON validé de façon déclarative (#[derive(Validate)]) : 422 avec un message par champ
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T> where T: DeserializeOwned + Validate, S: Send + Sync {
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await.map_err(|_| AppError::BadRequest)?;
+        value.validate()?;
+        Ok(ValidatedJson(value))
+    }
+}
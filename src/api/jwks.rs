@@ -0,0 +1,5 @@
+use axum::{extract::State, Json};
+use serde_json::Value;
+use crate::utils::config::Config;
+
+pub async fn jwks(State(cfg): State<Config>) -> Json<Value> { Json(cfg.jwt_keys.jwks()) }
@@ -0,0 +1,49 @@
+use axum::{extract::{FromRef, Path, State}, routing::post, Json, Router};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use validator::Validate;
+use crate::api::extract::{AuthUser, ValidatedJson};
+use crate::utils::{config::Config, error::AppError};
+
+// Pourcentage de visionnage à partir duquel la leçon est considérée comme terminée
+const COMPLETION_THRESHOLD: f64 = 90.0;
+
+#[derive(Clone, FromRef)]
+pub struct LessonsState { pub pool: PgPool, pub cfg: Config }
+
+#[derive(Deserialize, Validate)]
+pub struct ProgressRequest {
+    #[validate(range(min = 0.0, max = 100.0, message = "Must be between 0 and 100"))]
+    pub progress: f64,
+}
+
+#[derive(Serialize)]
+pub struct ProgressResponse { pub lesson_id: String, pub completed: bool }
+
+pub fn routes(pool: PgPool, cfg: Config) -> Router {
+    Router::new()
+        .route("/:id/progress", post(record_progress))
+        .with_state(LessonsState { pool, cfg })
+}
+
+// Le lecteur envoie l'avancement (0-100) ; seule la complétion est conservée (lesson_progress),
+// elle alimente les cours terminés du profil public et l'avancement du détail d'un cours
+async fn record_progress(user: AuthUser, Path(id): Path<Uuid>, State(state): State<LessonsState>, ValidatedJson(req): ValidatedJson<ProgressRequest>) -> Result<Json<ProgressResponse>, AppError> {
+    let row = sqlx::query("SELECT EXISTS (SELECT 1 FROM enrollments e WHERE e.user_id = $2 AND e.course_id = m.course_id) AS enrolled, EXISTS (SELECT 1 FROM lesson_progress p WHERE p.user_id = $2 AND p.lesson_id = l.id) AS completed FROM lessons l JOIN modules m ON m.id = l.module_id WHERE l.id = $1")
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::NotFound) };
+    // Les aperçus gratuits se regardent sans inscription mais ne comptent pas dans l'avancement
+    if !row.get::<bool, _>("enrolled") { return Err(AppError::Forbidden) }
+
+    let mut completed: bool = row.get("completed");
+    if !completed && req.progress >= COMPLETION_THRESHOLD {
+        sqlx::query("INSERT INTO lesson_progress (user_id, lesson_id) VALUES ($1, $2) ON CONFLICT DO NOTHING").bind(user.id).bind(id).execute(&state.pool).await.map_err(|_| AppError::Internal)?;
+        completed = true;
+    }
+    Ok(Json(ProgressResponse { lesson_id: id.to_string(), completed }))
+}
@@ -1,19 +1,36 @@
 use axum::{routing::get, Router};
+use tower_http::services::ServeDir;
 use sqlx::PgPool;
 use crate::utils::config::Config;
 
+pub mod admin;
+pub mod extract;
 pub mod health;
+pub mod jwks;
+pub mod oauth;
 pub mod auth;
 pub mod profile;
 pub mod courses;
+pub mod lessons;
 pub mod stripe;
+pub mod two_factor;
+pub mod users;
 
 pub fn build_router(pool: PgPool, cfg: Config) -> Router {
-    Router::new()
+    let router = Router::new()
         .route("/api/health", get(health::health))
+        .route("/.well-known/jwks.json", get(jwks::jwks).with_state(cfg.clone()))
         .nest("/api/auth", auth::routes(pool.clone(), cfg.clone()))
         .nest("/api/profile", profile::routes(pool.clone(), cfg.clone()))
+        // Chemin utilisé par le frontend (profileApi)
+        .nest("/api/user/profile", profile::routes(pool.clone(), cfg.clone()))
+        .nest("/api/user/avatar", profile::avatar_routes(pool.clone(), cfg.clone()))
         .nest("/api/courses", courses::routes(pool.clone(), cfg.clone()))
+        .nest("/api/lessons", lessons::routes(pool.clone(), cfg.clone()))
         .nest("/api/stripe", stripe::routes(pool.clone(), cfg.clone()))
+        .nest("/api/users", users::routes(pool.clone(), cfg.clone()))
+        .nest("/api/admin", admin::routes(pool.clone(), cfg.clone()));
+    // Sans S3, les fichiers publics (avatars) sont servis directement par l'API
+    if cfg.s3_config.is_none() { router.nest_service("/uploads", ServeDir::new(&cfg.upload_dir)) } else { router }
 }
 
@@ -0,0 +1,51 @@
+use axum::{Router, routing::get, extract::{Path, Query, State}, response::Redirect, Json};
+use serde::Deserialize;
+use crate::api::auth::{complete_login, AuthState, LoginOutcome};
+use crate::api::extract::Client;
+use crate::service::{oidc_service, session_service::ClientInfo};
+use crate::utils::error::AppError;
+
+#[derive(Deserialize)]
+pub struct CallbackParams { pub code: Option<String>, pub state: Option<String>, pub error: Option<String> }
+
+pub fn routes() -> Router<AuthState> {
+    Router::new()
+        .route("/providers", get(providers))
+        .route("/:provider/authorize", get(authorize))
+        .route("/:provider/callback", get(callback))
+}
+
+async fn providers(State(state): State<AuthState>) -> Json<Vec<String>> {
+    Json(state.cfg.oauth_providers.iter().map(|p| p.name.clone()).collect())
+}
+
+async fn authorize(Path(provider): Path<String>, State(state): State<AuthState>) -> Result<Redirect, AppError> {
+    let provider = oidc_service::find_provider(&state.cfg, &provider)?;
+    let url = oidc_service::authorization_url(&state.pool, &state.cfg, provider).await?;
+    Ok(Redirect::to(&url))
+}
+
+// Le navigateur revient du fournisseur ; on le renvoie vers le frontend avec les jetons dans le
+// fragment d'URL (jamais transmis aux serveurs ni journalisé) ou avec un code d'erreur.
+async fn callback(Path(provider): Path<String>, State(state): State<AuthState>, Client(client): Client, Query(params): Query<CallbackParams>) -> Redirect {
+    let base = format!("{}/auth/callback", state.cfg.frontend_url);
+    let fragment = match handle_callback(&state, &provider, params, &client).await {
+        Ok(LoginOutcome::Tokens(t)) => format!("token={}&refresh_token={}", t.token, t.refresh_token.unwrap_or_default()),
+        Ok(LoginOutcome::MfaRequired(c)) => format!("mfa_token={}&enrollment_required={}", c.mfa_token, c.enrollment_required),
+        Err(AppError::Conflict) => "error=email_not_verified".to_string(),
+        Err(e) => {
+            tracing::warn!(%provider, error = %e, "oauth callback failed");
+            "error=oauth_failed".to_string()
+        }
+    };
+    Redirect::to(&format!("{base}#{fragment}"))
+}
+
+async fn handle_callback(state: &AuthState, provider: &str, params: CallbackParams, client: &ClientInfo) -> Result<LoginOutcome, AppError> {
+    if params.error.is_some() { return Err(AppError::Unauthorized) }
+    let (Some(code), Some(oauth_state)) = (params.code, params.state) else { return Err(AppError::BadRequest) };
+    let provider = oidc_service::find_provider(&state.cfg, provider)?;
+    let identity = oidc_service::exchange_code(&state.pool, &state.cfg, provider, &code, &oauth_state).await?;
+    let user = oidc_service::link_or_create_user(&state.pool, &identity).await?;
+    complete_login(state, user.id, &user.role, user.totp_enabled, client).await
+}
@@ -1,34 +1,173 @@
-use axum::{Router, routing::put, extract::State, Json};
+use axum::{Router, routing::{get, post, put}, extract::{multipart::MultipartError, DefaultBodyLimit, FromRef, Multipart, Path, Query, State}, http::{header, StatusCode}, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
-use crate::utils::{config::Config, error::AppError};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use validator::Validate;
+use crate::api::{extract::{AuthUser, ValidatedJson}, users::PUBLIC_FIELDS};
+use crate::service::{auth_service, avatar_service::{self, AvatarDto}, email_service, notification_service::{self, Category, Preferences, PreferencesUpdate}, privacy_service, session_service, two_factor_service};
+use crate::utils::{config::Config, error::AppError, password::{hash_password, verify_password}, password_policy, validation::{self, normalize_email}};
 
-#[derive(Clone)]
-pub struct ProfileState { pub pool: PgPool, pub _cfg: Config }
+#[derive(Clone, FromRef)]
+pub struct ProfileState { pub pool: PgPool, pub cfg: Config }
 
-#[derive(Deserialize)]
+// Champ absent : inchangé ; chaîne vide : effacé
+#[derive(Deserialize, Validate)]
 pub struct ProfileUpdate {
+    // Le frontend envoie `name`
+    #[serde(alias = "name")]
+    #[validate(length(max = 100, message = "Must be at most 100 characters"))]
     pub full_name: Option<String>,
+    #[validate(length(max = 2000, message = "Must be at most 2000 characters"))]
     pub bio: Option<String>,
+    #[validate(length(max = 500, message = "Must be at most 500 characters"), custom(function = "validation::http_url"))]
     pub avatar_url: Option<String>,
+    #[validate(length(max = 100, message = "Must be at most 100 characters"))]
     pub job_title: Option<String>,
+    #[validate(length(max = 100, message = "Must be at most 100 characters"))]
     pub company: Option<String>,
+    #[validate(length(max = 100, message = "Must be at most 100 characters"))]
     pub city: Option<String>,
+    #[validate(length(max = 100, message = "Must be at most 100 characters"))]
     pub country: Option<String>,
+    #[validate(length(max = 255, message = "Must be at most 255 characters"), custom(function = "validation::http_url"))]
     pub linkedin_url: Option<String>,
+    #[validate(length(max = 255, message = "Must be at most 255 characters"), custom(function = "validation::http_url"))]
     pub website_url: Option<String>,
+    #[validate(custom(function = "validation::pcsoft_experience"))]
     pub pcsoft_experience: Option<String>,
+    #[validate(custom(function = "validation::e164_phone"))]
     pub phone_number: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest { pub current_password: String, pub new_password: String }
+
+#[derive(Deserialize, Validate)]
+pub struct ChangeEmailRequest {
+    #[validate(custom(function = "validation::email"))]
+    pub email: String,
+    pub current_password: String,
+}
+
+// Mot de passe, code de second facteur ou, pour un compte lié à un fournisseur externe, une connexion récente
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest { pub current_password: Option<String>, pub code: Option<String> }
+
+// Délai pendant lequel une connexion par fournisseur externe vaut confirmation d'identité
+const REAUTH_WINDOW_MINUTES: i32 = 5;
+
+#[derive(Serialize)]
+pub struct ExportResponse { pub id: String, pub status: &'static str }
+
+#[derive(Deserialize, Validate)]
+pub struct VisibilityUpdate {
+    #[validate(custom(function = "known_public_fields"))]
+    pub public_fields: Vec<String>,
+}
+
+fn known_public_fields(fields: &[String]) -> Result<(), validator::ValidationError> {
+    let Some(unknown) = fields.iter().find(|f| !PUBLIC_FIELDS.iter().any(|(key, _)| key == f)) else { return Ok(()) };
+    let allowed: Vec<&str> = PUBLIC_FIELDS.iter().map(|(key, _)| *key).collect();
+    Err(validation::error("unknown_field", format!("Unknown field {unknown}; allowed: {}", allowed.join(", "))))
+}
+
+#[derive(Deserialize)]
+pub struct UnsubscribeQuery { pub token: String }
+
+#[derive(Serialize)]
+pub struct UnsubscribeResult { pub category: Category, pub unsubscribed: bool }
+
 #[derive(Serialize)]
 pub struct ProfileResult { pub ok: bool }
 
-pub fn routes(pool: PgPool, cfg: Config) -> Router { Router::new().route("/", put(update)).with_state(ProfileState { pool, _cfg: cfg }) }
+#[derive(Serialize)]
+pub struct ProfileDto {
+    pub id: String,
+    pub email: String,
+    pub name: Option<String>,
+    pub role: String,
+    pub avatar_url: Option<String>,
+    pub bio: Option<String>,
+    pub job_title: Option<String>,
+    pub company: Option<String>,
+    pub city: Option<String>,
+    pub country: Option<String>,
+    pub linkedin_url: Option<String>,
+    pub website_url: Option<String>,
+    pub pcsoft_experience: Option<String>,
+    pub phone_number: Option<String>,
+    pub email_verified: bool,
+    pub two_factor_enabled: bool,
+    pub enrollments_count: i64,
+    pub public_fields: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+pub fn routes(pool: PgPool, cfg: Config) -> Router {
+    Router::new()
+        .route("/", get(get_profile).put(update).delete(delete_account))
+        .route("/export", post(request_export))
+        .route("/export/:token", get(download_export))
+        .route("/password", put(change_password))
+        .route("/email", put(change_email))
+        .route("/visibility", put(update_visibility))
+        .route("/notifications", get(get_notifications).put(update_notifications))
+        .route("/notifications/unsubscribe", post(unsubscribe))
+        .route("/avatar", post(upload_avatar).layer(DefaultBodyLimit::max(AVATAR_BODY_LIMIT)))
+        .with_state(ProfileState { pool, cfg })
+}
+
+// Le frontend envoie l'avatar sur /api/user/avatar
+pub fn avatar_routes(pool: PgPool, cfg: Config) -> Router {
+    Router::new().route("/", post(upload_avatar).layer(DefaultBodyLimit::max(AVATAR_BODY_LIMIT))).with_state(ProfileState { pool, cfg })
+}
+
+// Marge pour l'enveloppe multipart autour du fichier
+const AVATAR_BODY_LIMIT: usize = avatar_service::MAX_AVATAR_BYTES + 64 * 1024;
+
+pub async fn fetch_profile(pool: &PgPool, user_id: Uuid) -> Result<ProfileDto, AppError> {
+    let row = sqlx::query("SELECT u.id, u.email, u.full_name, COALESCE(u.role, 'user') AS role, u.avatar_url, u.bio, u.job_title, u.company, u.city, u.country, u.linkedin_url, u.website_url, u.pcsoft_experience::text AS pcsoft_experience, u.phone_number, u.email_verified_at IS NOT NULL AS email_verified, u.totp_enabled_at IS NOT NULL AS two_factor_enabled, u.created_at, u.updated_at, u.last_login_at, (SELECT count(*) FROM enrollments e WHERE e.user_id = u.id) AS enrollments_count, u.public_profile_fields FROM users u WHERE u.id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(r) = row else { return Err(AppError::NotFound) };
+    let id: Uuid = r.get("id");
+    Ok(ProfileDto {
+        id: id.to_string(),
+        email: r.get("email"),
+        name: r.get("full_name"),
+        role: r.get("role"),
+        avatar_url: r.get("avatar_url"),
+        bio: r.get("bio"),
+        job_title: r.get("job_title"),
+        company: r.get("company"),
+        city: r.get("city"),
+        country: r.get("country"),
+        linkedin_url: r.get("linkedin_url"),
+        website_url: r.get("website_url"),
+        pcsoft_experience: r.get("pcsoft_experience"),
+        phone_number: r.get("phone_number"),
+        email_verified: r.get("email_verified"),
+        two_factor_enabled: r.get("two_factor_enabled"),
+        enrollments_count: r.get("enrollments_count"),
+        public_fields: r.get("public_profile_fields"),
+        created_at: r.get("created_at"),
+        updated_at: r.get("updated_at"),
+        last_login_at: r.get("last_login_at"),
+    })
+}
+
+async fn get_profile(user: AuthUser, State(state): State<ProfileState>) -> Result<Json<ProfileDto>, AppError> {
+    Ok(Json(fetch_profile(&state.pool, user.id).await?))
+}
 
-async fn update(State(state): State<ProfileState>, Json(body): Json<ProfileUpdate>) -> Result<Json<ProfileResult>, AppError> {
+async fn update(user: AuthUser, State(state): State<ProfileState>, ValidatedJson(body): ValidatedJson<ProfileUpdate>) -> Result<Json<ProfileDto>, AppError> {
     let _ = sqlx::query(
-        "UPDATE users SET full_name = COALESCE($1, full_name), bio = COALESCE($2, bio), avatar_url = COALESCE($3, avatar_url), job_title = COALESCE($4, job_title), company = COALESCE($5, company), city = COALESCE($6, city), country = COALESCE($7, country), linkedin_url = COALESCE($8, linkedin_url), website_url = COALESCE($9, website_url), pcsoft_experience = COALESCE($10, pcsoft_experience), phone_number = COALESCE($11, phone_number) WHERE id = $12"
+        "UPDATE users SET full_name = NULLIF(COALESCE($1, full_name), ''), bio = NULLIF(COALESCE($2, bio), ''), avatar_url = NULLIF(COALESCE($3, avatar_url), ''), job_title = NULLIF(COALESCE($4, job_title), ''), company = NULLIF(COALESCE($5, company), ''), city = NULLIF(COALESCE($6, city), ''), country = NULLIF(COALESCE($7, country), ''), linkedin_url = NULLIF(COALESCE($8, linkedin_url), ''), website_url = NULLIF(COALESCE($9, website_url), ''), pcsoft_experience = CASE WHEN $10::text IS NULL THEN pcsoft_experience ELSE NULLIF($10, '')::pcsoft_experience END, phone_number = NULLIF(COALESCE($11, phone_number), ''), updated_at = now() WHERE id = $12"
     )
     .bind(&body.full_name)
     .bind(&body.bio)
@@ -41,10 +180,141 @@ async fn update(State(state): State<ProfileState>, Json(body): Json<ProfileUpdat
     .bind(&body.website_url)
     .bind(&body.pcsoft_experience)
     .bind(&body.phone_number)
-    .bind(uuid::Uuid::nil())
+    .bind(user.id)
     .execute(&state.pool)
     .await
     .map_err(|_| AppError::Internal)?;
+    Ok(Json(fetch_profile(&state.pool, user.id).await?))
+}
+
+// Choix des champs affichés sur la page publique (GET /api/users/:id/public)
+async fn update_visibility(user: AuthUser, State(state): State<ProfileState>, ValidatedJson(req): ValidatedJson<VisibilityUpdate>) -> Result<Json<ProfileDto>, AppError> {
+    let mut fields = req.public_fields;
+    fields.sort();
+    fields.dedup();
+    sqlx::query("UPDATE users SET public_profile_fields = $1, updated_at = now() WHERE id = $2").bind(&fields).bind(user.id).execute(&state.pool).await.map_err(|_| AppError::Internal)?;
+    Ok(Json(fetch_profile(&state.pool, user.id).await?))
+}
+
+async fn get_notifications(user: AuthUser, State(state): State<ProfileState>) -> Result<Json<Preferences>, AppError> {
+    Ok(Json(notification_service::get_preferences(&state.pool, user.id).await?))
+}
+
+async fn update_notifications(user: AuthUser, State(state): State<ProfileState>, Json(req): Json<PreferencesUpdate>) -> Result<Json<Preferences>, AppError> {
+    Ok(Json(notification_service::update_preferences(&state.pool, user.id, &req).await?))
+}
+
+// Cible de List-Unsubscribe : le client mail envoie "List-Unsubscribe=One-Click" en POST, sans session (RFC 8058) ;
+// la page de désinscription du frontend appelle la même route
+async fn unsubscribe(Query(query): Query<UnsubscribeQuery>, State(state): State<ProfileState>) -> Result<Json<UnsubscribeResult>, AppError> {
+    let category = notification_service::unsubscribe(&state.pool, &state.cfg, &query.token).await?;
+    Ok(Json(UnsubscribeResult { category, unsubscribed: true }))
+}
+
+struct Credentials { email: String, full_name: Option<String> }
+
+// Ressaisie du mot de passe actuel avant une modification sensible, soumise au même verrouillage que la connexion
+async fn confirm_current_password(state: &ProfileState, user_id: Uuid, password: &str) -> Result<Credentials, AppError> {
+    let row = sqlx::query("SELECT email, full_name, password_hash FROM users WHERE id = $1").bind(user_id).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::Unauthorized) };
+    auth_service::check_lockout(&state.pool, user_id).await?;
+    if verify_password(password, row.get("password_hash")).is_err() {
+        auth_service::record_failed_login(&state.pool, &state.cfg, user_id).await?;
+        return Err(AppError::field("current_password", "Current password is incorrect"));
+    }
+    Ok(Credentials { email: row.get("email"), full_name: row.get("full_name") })
+}
+
+async fn change_password(user: AuthUser, State(state): State<ProfileState>, Json(req): Json<ChangePasswordRequest>) -> Result<Json<ProfileResult>, AppError> {
+    user.deny_impersonation()?;
+    let account = confirm_current_password(&state, user.id, &req.current_password).await?;
+    password_policy::check(&state.cfg, &req.new_password, &[&account.email, account.full_name.as_deref().unwrap_or_default()]).await?;
+    let password_hash = hash_password(&req.new_password)?;
+
+    let mut tx = state.pool.begin().await.map_err(|_| AppError::Internal)?;
+    sqlx::query("UPDATE users SET password_hash = $1, updated_at = now() WHERE id = $2").bind(&password_hash).bind(user.id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    // Les liens de réinitialisation encore en attente sont invalidés
+    sqlx::query("UPDATE password_reset_tokens SET used_at = now() WHERE user_id = $1 AND used_at IS NULL").bind(user.id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    session_service::revoke_all_sessions(&mut *tx, user.id, user.session_id).await?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+
+    let body = "Bonjour,\n\nLe mot de passe de votre compte WindevExpert vient d'être modifié et vos autres sessions ont été déconnectées.\n\nSi vous n'êtes pas à l'origine de ce changement, réinitialisez immédiatement votre mot de passe.".to_string();
+    email_service::spawn_send(state.cfg.smtp_config.clone(), account.email, "Votre mot de passe a été modifié".to_string(), body);
     Ok(Json(ProfileResult { ok: true }))
 }
 
+async fn change_email(user: AuthUser, State(state): State<ProfileState>, ValidatedJson(req): ValidatedJson<ChangeEmailRequest>) -> Result<Json<ProfileResult>, AppError> {
+    user.deny_impersonation()?;
+    let email = normalize_email(&req.email);
+    let account = confirm_current_password(&state, user.id, &req.current_password).await?;
+    if email == account.email { return Err(AppError::field("email", "This is already your email address")) }
+
+    // users.email ne change qu'à la confirmation du lien : une faute de frappe ne prive pas l'utilisateur de son compte
+    let taken = sqlx::query("SELECT 1 FROM users WHERE email = $1").bind(&email).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
+    if taken.is_some() { return Err(AppError::Conflict) }
+    // Seule la dernière adresse demandée peut être confirmée
+    sqlx::query("UPDATE email_verification_tokens SET used_at = now() WHERE user_id = $1 AND used_at IS NULL AND email <> $2").bind(user.id).bind(&account.email).execute(&state.pool).await.map_err(|_| AppError::Internal)?;
+    auth_service::send_email_verification(&state.pool, &state.cfg, user.id, &email).await?;
+
+    let body = format!("Bonjour,\n\nUn changement de l'adresse email de votre compte WindevExpert vers {email} a été demandé. Il prendra effet dès que la nouvelle adresse sera confirmée.\n\nSi vous n'êtes pas à l'origine de cette demande, changez votre mot de passe et contactez-nous en répondant à ce message.");
+    email_service::spawn_send(state.cfg.smtp_config.clone(), account.email, "Changement d'adresse email demandé".to_string(), body);
+    Ok(Json(ProfileResult { ok: true }))
+}
+
+async fn upload_avatar(user: AuthUser, State(state): State<ProfileState>, mut multipart: Multipart) -> Result<Json<AvatarDto>, AppError> {
+    let too_large = || AppError::field("avatar", format!("The file must not exceed {} MB", avatar_service::MAX_AVATAR_BYTES / 1024 / 1024));
+    // Le dépassement de DefaultBodyLimit remonte comme une erreur multipart 413
+    let multipart_error = |e: MultipartError| if e.status() == StatusCode::PAYLOAD_TOO_LARGE { too_large() } else { AppError::BadRequest };
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() != Some("avatar") { continue }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            if bytes.len() + chunk.len() > avatar_service::MAX_AVATAR_BYTES { return Err(too_large()) }
+            bytes.extend_from_slice(&chunk);
+        }
+        return Ok(Json(avatar_service::replace_avatar(&state.pool, &state.cfg, user.id, bytes).await?));
+    }
+    Err(AppError::field("avatar", "No file was sent"))
+}
+
+async fn request_export(user: AuthUser, State(state): State<ProfileState>) -> Result<(StatusCode, Json<ExportResponse>), AppError> {
+    let id = privacy_service::request_export(&state.pool, &state.cfg, user.id).await?;
+    Ok((StatusCode::ACCEPTED, Json(ExportResponse { id: id.to_string(), status: "pending" })))
+}
+
+// Lien reçu par email : le jeton suffit, sans session
+async fn download_export(Path(token): Path<String>, State(state): State<ProfileState>) -> Result<impl IntoResponse, AppError> {
+    let archive = privacy_service::download_export(&state.pool, &token).await?;
+    let headers = [(header::CONTENT_TYPE, "application/zip"), (header::CONTENT_DISPOSITION, "attachment; filename=\"windevexpert-export.zip\""), (header::CACHE_CONTROL, "no-store")];
+    Ok((headers, archive))
+}
+
+// Un compte créé via Google ou GitHub n'a qu'un mot de passe aléatoire jamais communiqué
+async fn confirm_deletion_identity(state: &ProfileState, user: &AuthUser, req: &DeleteAccountRequest) -> Result<Credentials, AppError> {
+    if let Some(password) = &req.current_password { return confirm_current_password(state, user.id, password).await }
+    let row = sqlx::query("SELECT email, full_name, EXISTS (SELECT 1 FROM provider_identities p WHERE p.user_id = u.id) AS external, EXISTS (SELECT 1 FROM sessions s WHERE s.id = $2 AND s.user_id = u.id AND s.created_at > now() - make_interval(mins => $3)) AS fresh FROM users u WHERE id = $1")
+        .bind(user.id)
+        .bind(user.session_id)
+        .bind(REAUTH_WINDOW_MINUTES)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::Unauthorized) };
+    let account = Credentials { email: row.get("email"), full_name: row.get("full_name") };
+    if let Some(code) = &req.code {
+        two_factor_service::confirm_second_factor(&state.pool, &state.cfg, user.id, code).await.map_err(|e| match e {
+            AppError::Unauthorized => AppError::field("code", "Invalid code"),
+            e => e,
+        })?;
+        return Ok(account);
+    }
+    if row.get("external") && row.get("fresh") { return Ok(account) }
+    Err(AppError::field("current_password", "Confirm with your password, a two-factor code or by signing in again"))
+}
+
+async fn delete_account(user: AuthUser, State(state): State<ProfileState>, Json(req): Json<DeleteAccountRequest>) -> Result<StatusCode, AppError> {
+    user.deny_impersonation()?;
+    let account = confirm_deletion_identity(&state, &user, &req).await?;
+    privacy_service::request_deletion(&state.pool, &state.cfg, user.id, &account.email).await?;
+    Ok(StatusCode::ACCEPTED)
+}
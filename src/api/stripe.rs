@@ -1,4 +1,4 @@
-use axum::{Router, routing::post, extract::{State}, body::Bytes};
+use axum::{Router, routing::post, extract::{FromRef, State}, body::Bytes};
 use axum::http::HeaderMap;
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
@@ -6,7 +6,7 @@ use serde_json::Value;
 use sqlx::PgPool;
 use crate::utils::{config::Config, error::AppError};
 
-#[derive(Clone)]
+#[derive(Clone, FromRef)]
 pub struct StripeState { pub pool: PgPool, pub cfg: Config }
 
 pub fn routes(pool: PgPool, cfg: Config) -> Router {
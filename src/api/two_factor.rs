@@ -0,0 +1,119 @@
+use axum::{Router, routing::post, extract::State, middleware, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use crate::api::auth::{AuthState, LoginResponse};
+use crate::api::extract::{AuthUser, Client, MfaSubject, ROLE_ADMIN, ROLE_MFA_PENDING};
+use crate::service::{auth_service, two_factor_service};
+use crate::utils::{error::AppError, jwt::validate_token, rate_limit::{self, RateLimit}, totp};
+
+const TOTP_ISSUER: &str = "WindevExpert";
+
+#[derive(Deserialize)]
+pub struct CodeRequest { pub code: String }
+
+#[derive(Deserialize)]
+pub struct VerifyRequest { pub mfa_token: String, pub code: String }
+
+#[derive(Serialize)]
+pub struct SetupResponse { pub secret: String, pub otpauth_uri: String }
+
+#[derive(Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+    // Présent quand l'enrôlement termine une connexion en attente de 2FA
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub session: Option<LoginResponse>,
+}
+
+// Les routes qui vérifient un code existant partagent le quota des routes d'identification
+pub fn routes(limiter: RateLimit) -> Router<AuthState> {
+    let limited = || middleware::from_fn_with_state(limiter.clone(), rate_limit::limit);
+    Router::new()
+        .route("/setup", post(setup))
+        .route("/enable", post(enable))
+        .route("/verify", post(verify).layer(limited()))
+        .route("/disable", post(disable).layer(limited()))
+        .route("/recovery-codes", post(regenerate_recovery_codes).layer(limited()))
+}
+
+async fn setup(subject: MfaSubject, State(state): State<AuthState>) -> Result<Json<SetupResponse>, AppError> {
+    let secret = totp::generate_secret();
+    // Le secret reste en attente tant qu'un premier code n'a pas été validé via /enable
+    let row = sqlx::query("UPDATE users SET totp_secret = $2, totp_last_step = NULL WHERE id = $1 AND totp_enabled_at IS NULL RETURNING email")
+        .bind(subject.id)
+        .bind(&secret)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::Conflict) };
+    let email: String = row.get("email");
+    let otpauth_uri = totp::otpauth_uri(TOTP_ISSUER, &email, &secret);
+    Ok(Json(SetupResponse { secret, otpauth_uri }))
+}
+
+async fn enable(subject: MfaSubject, State(state): State<AuthState>, Client(client): Client, Json(req): Json<CodeRequest>) -> Result<Json<RecoveryCodesResponse>, AppError> {
+    let row = sqlx::query("SELECT totp_secret, role FROM users WHERE id = $1 AND totp_enabled_at IS NULL AND totp_secret IS NOT NULL")
+        .bind(subject.id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::Conflict) };
+    let secret: String = row.get("totp_secret");
+    let role: Option<String> = row.get("role");
+    let step = totp::verify(&secret, &req.code, chrono::Utc::now().timestamp() as u64, None).ok_or(AppError::Unauthorized)?;
+
+    let mut tx = state.pool.begin().await.map_err(|_| AppError::Internal)?;
+    sqlx::query("UPDATE users SET totp_enabled_at = now(), totp_last_step = $2 WHERE id = $1")
+        .bind(subject.id)
+        .bind(step)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let recovery_codes = two_factor_service::regenerate_recovery_codes(&mut *tx, subject.id).await?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+    tracing::info!(user_id = %subject.id, "two-factor authentication enabled");
+
+    let session = if subject.pending {
+        Some(auth_service::issue_tokens(&state.pool, &state.cfg, subject.id, role.as_deref().unwrap_or("user"), &client).await?.into())
+    } else { None };
+    Ok(Json(RecoveryCodesResponse { recovery_codes, session }))
+}
+
+async fn verify(State(state): State<AuthState>, Client(client): Client, Json(req): Json<VerifyRequest>) -> Result<Json<LoginResponse>, AppError> {
+    let data = validate_token(&req.mfa_token, &state.cfg.jwt_keys)?;
+    if data.claims.role != ROLE_MFA_PENDING { return Err(AppError::Unauthorized) }
+    let user_id = uuid::Uuid::parse_str(&data.claims.sub).map_err(|_| AppError::Unauthorized)?;
+    let (jti, expires_at) = (data.claims.jti.as_str(), data.claims.exp as i64);
+    if !two_factor_service::mfa_token_usable(&state.pool, jti).await? { return Err(AppError::Unauthorized) }
+    // Les échecs du second facteur comptent pour le verrouillage du compte, comme ceux du mot de passe
+    auth_service::check_lockout(&state.pool, user_id).await?;
+    if !two_factor_service::verify_second_factor(&state.pool, user_id, &req.code).await? {
+        two_factor_service::record_mfa_failure(&state.pool, jti, user_id, expires_at).await?;
+        auth_service::record_failed_login(&state.pool, &state.cfg, user_id).await?;
+        return Err(AppError::Unauthorized);
+    }
+    if !two_factor_service::consume_mfa_token(&state.pool, jti, user_id, expires_at).await? { return Err(AppError::Unauthorized) }
+
+    let row = sqlx::query("SELECT role FROM users WHERE id = $1").bind(user_id).fetch_optional(&state.pool).await.map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::Unauthorized) };
+    let role: Option<String> = row.get("role");
+    let tokens = auth_service::issue_tokens(&state.pool, &state.cfg, user_id, role.as_deref().unwrap_or("user"), &client).await?;
+    Ok(Json(tokens.into()))
+}
+
+async fn disable(user: AuthUser, State(state): State<AuthState>, Json(req): Json<CodeRequest>) -> Result<(), AppError> {
+    if state.cfg.require_admin_2fa && user.role == ROLE_ADMIN { return Err(AppError::Forbidden) }
+    two_factor_service::confirm_second_factor(&state.pool, &state.cfg, user.id, &req.code).await?;
+    let mut tx = state.pool.begin().await.map_err(|_| AppError::Internal)?;
+    sqlx::query("UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL WHERE id = $1").bind(user.id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1").bind(user.id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+    tracing::info!(user_id = %user.id, "two-factor authentication disabled");
+    Ok(())
+}
+
+async fn regenerate_recovery_codes(user: AuthUser, State(state): State<AuthState>, Json(req): Json<CodeRequest>) -> Result<Json<RecoveryCodesResponse>, AppError> {
+    two_factor_service::confirm_second_factor(&state.pool, &state.cfg, user.id, &req.code).await?;
+    let recovery_codes = two_factor_service::regenerate_recovery_codes(&state.pool, user.id).await?;
+    Ok(Json(RecoveryCodesResponse { recovery_codes, session: None }))
+}
@@ -0,0 +1,79 @@
+use axum::{extract::{FromRef, Path, State}, routing::get, Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::service::settings_service;
+use crate::utils::{config::Config, error::AppError};
+
+#[derive(Clone, FromRef)]
+pub struct UsersState { pub pool: PgPool, pub cfg: Config }
+
+// Champs de profil que l'utilisateur peut rendre publics (clé -> colonne de users)
+pub const PUBLIC_FIELDS: &[(&str, &str)] = &[
+    ("name", "full_name"),
+    ("avatar_url", "avatar_url"),
+    ("bio", "bio"),
+    ("job_title", "job_title"),
+    ("company", "company"),
+    ("city", "city"),
+    ("country", "country"),
+    ("linkedin_url", "linkedin_url"),
+    ("website_url", "website_url"),
+    ("pcsoft_experience", "pcsoft_experience"),
+];
+
+#[derive(Serialize)]
+pub struct CompletedCourseDto { pub id: String, pub title: String, pub slug: String, pub thumbnail_url: Option<String>, pub completed_at: DateTime<Utc> }
+
+#[derive(Serialize)]
+pub struct PublicProfileDto {
+    pub id: String,
+    // Avatar de l'utilisateur s'il est public, sinon l'image par défaut choisie par les admins
+    pub avatar_url: Option<String>,
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+    pub completed_courses: Vec<CompletedCourseDto>,
+    pub review_count: i64,
+}
+
+pub fn routes(pool: PgPool, cfg: Config) -> Router {
+    Router::new()
+        .route("/:id/public", get(public_profile))
+        .with_state(UsersState { pool, cfg })
+}
+
+async fn public_profile(Path(id): Path<String>, State(state): State<UsersState>) -> Result<Json<PublicProfileDto>, AppError> {
+    let user_id = Uuid::parse_str(&id).map_err(|_| AppError::NotFound)?;
+    let row = sqlx::query("SELECT full_name, avatar_url, bio, job_title, company, city, country, linkedin_url, website_url, pcsoft_experience::text AS pcsoft_experience, public_profile_fields, (SELECT count(*) FROM reviews r WHERE r.user_id = u.id) AS review_count FROM users u WHERE id = $1 AND deleted_at IS NULL AND deletion_requested_at IS NULL")
+        .bind(user_id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::NotFound) };
+
+    let visible: Vec<String> = row.get("public_profile_fields");
+    let mut fields = Map::new();
+    for (key, column) in PUBLIC_FIELDS.iter().filter(|(key, _)| *key != "avatar_url" && visible.iter().any(|v| v == key)) {
+        if let Some(value) = row.get::<Option<String>, _>(*column) { fields.insert(key.to_string(), Value::String(value)); }
+    }
+    let own_avatar = row.get::<Option<String>, _>("avatar_url").filter(|_| visible.iter().any(|v| v == "avatar_url"));
+    let avatar_url = match own_avatar {
+        Some(url) => Some(url),
+        None => settings_service::get_string(&state.pool, settings_service::DEFAULT_AVATAR_URL).await?,
+    };
+
+    // Cours terminés : cours achetés dont toutes les leçons sont marquées comme faites
+    let courses = sqlx::query("SELECT c.id, c.title, c.slug, c.thumbnail_url, max(p.completed_at) AS completed_at FROM enrollments e JOIN courses c ON c.id = e.course_id JOIN modules m ON m.course_id = c.id JOIN lessons l ON l.module_id = m.id LEFT JOIN lesson_progress p ON p.lesson_id = l.id AND p.user_id = e.user_id WHERE e.user_id = $1 GROUP BY c.id HAVING count(*) = count(p.lesson_id) ORDER BY max(p.completed_at) DESC")
+        .bind(user_id)
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let completed_courses = courses.into_iter().map(|r| {
+        let id: Uuid = r.get("id");
+        CompletedCourseDto { id: id.to_string(), title: r.get("title"), slug: r.get("slug"), thumbnail_url: r.get("thumbnail_url"), completed_at: r.get("completed_at") }
+    }).collect();
+
+    Ok(Json(PublicProfileDto { id: user_id.to_string(), avatar_url, fields, completed_courses, review_count: row.get("review_count") }))
+}
@@ -16,15 +16,17 @@ async fn main() -> anyhow::Result<()> {
 
     let cfg = utils::config::Config::from_env()?;
     let pool = repository::db::init_pool(&cfg.database_url).await?;
+    service::admin_service::bootstrap_admin(&pool, &cfg).await?;
+    service::privacy_service::spawn_purge_task(pool.clone(), cfg.clone());
 
-    let cors = CorsLayer::new().allow_origin(utils::config::frontend_origin(&cfg.frontend_url)).allow_methods([http::Method::GET, http::Method::POST, http::Method::PUT, http::Method::DELETE]).allow_headers([http::header::CONTENT_TYPE, http::header::AUTHORIZATION]);
+    let cors = CorsLayer::new().allow_origin(utils::config::frontend_origin(&cfg.frontend_url)).allow_methods([http::Method::GET, http::Method::POST, http::Method::PUT, http::Method::DELETE]).allow_headers([http::header::CONTENT_TYPE, http::header::AUTHORIZATION]).expose_headers([http::header::RETRY_AFTER]);
 
     let app: Router = api::build_router(pool.clone(), cfg.clone()).layer(TraceLayer::new_for_http()).layer(cors);
 
     let addr: SocketAddr = format!("0.0.0.0:{}", cfg.port).parse().unwrap();
     let listener = TcpListener::bind(addr).await?;
     tracing::info!(%addr, "server listening");
-    axum::serve(listener, app).with_graceful_shutdown(utils::config::shutdown_signal()).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).with_graceful_shutdown(utils::config::shutdown_signal()).await?;
     Ok(())
 }
 
@@ -0,0 +1,40 @@
+use anyhow::{anyhow, bail, Result};
+use serde_json::json;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::service::settings_service;
+use crate::utils::{config::Config, token::hash_token};
+
+// Empreinte (email + hash Argon2, déjà salé) du dernier ADMIN_AUTH appliqué (app_settings)
+const ADMIN_AUTH_APPLIED: &str = "admin_auth_applied";
+
+// Crée ou met à jour le compte administrateur décrit par ADMIN_AUTH="email:<hash argon2>".
+// L'admin devient ainsi un vrai utilisateur (users.id) et se connecte par le chemin standard.
+pub async fn bootstrap_admin(pool: &PgPool, cfg: &Config) -> Result<()> {
+    let Some(admin_auth) = &cfg.admin_auth else { return Ok(()) };
+    let Some((email, secret)) = admin_auth.split_once(':') else { bail!("ADMIN_AUTH must be formatted as email:argon2_hash") };
+    let email = email.trim().to_lowercase();
+
+    // Un mot de passe en clair n'est plus accepté : il resterait lisible dans l'environnement du serveur
+    if !secret.starts_with("$argon2") { bail!("ADMIN_AUTH must contain an Argon2 hash, not a plaintext password") }
+    argon2::PasswordHash::new(secret).map_err(|e| anyhow!("invalid Argon2 hash in ADMIN_AUTH: {e}"))?;
+
+    let existing = sqlx::query("UPDATE users SET role = 'admin', updated_at = now() WHERE email = $1 RETURNING id").bind(&email).fetch_optional(pool).await?;
+    // Le mot de passe n'est écrit qu'à la création du compte ou quand ADMIN_AUTH change : un mot de passe
+    // modifié depuis le profil n'est pas écrasé à chaque redémarrage
+    let fingerprint = hash_token(&format!("{email}:{secret}"));
+    let admin_id: Uuid = match existing {
+        Some(row) => {
+            let id = row.get("id");
+            if settings_service::get_string(pool, ADMIN_AUTH_APPLIED).await?.as_deref() != Some(fingerprint.as_str()) {
+                sqlx::query("UPDATE users SET password_hash = $2 WHERE id = $1").bind(id).bind(secret).execute(pool).await?;
+                tracing::info!(%email, "bootstrap admin password reset from ADMIN_AUTH");
+            }
+            id
+        }
+        None => sqlx::query("INSERT INTO users (email, password_hash, role, email_verified_at) VALUES ($1, $2, 'admin', now()) RETURNING id").bind(&email).bind(secret).fetch_one(pool).await?.get("id"),
+    };
+    settings_service::set(pool, ADMIN_AUTH_APPLIED, json!(fingerprint), admin_id).await?;
+    tracing::info!(%email, "bootstrap admin account ensured");
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::service::session_service::ClientInfo;
+use crate::utils::{error::AppError, token::{generate_token, hash_token}};
+
+pub const KEY_PREFIX: &str = "wdx_";
+// Portées attribuables : "<zone>:read" autorise les GET, "<zone>:write" les autres méthodes
+pub const SCOPES: &[&str] = &["courses:read", "courses:write", "profile:read", "profile:write", "admin:read", "admin:write"];
+// Caractères de la clé affichés dans les listes, après "wdx_"
+const VISIBLE_CHARS: usize = 8;
+
+#[derive(Serialize)]
+pub struct ApiKeyDto {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub last_used_ip: Option<String>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// Titulaire d'une clé valide : l'utilisateur propriétaire, avec son rôle actuel
+pub struct KeyPrincipal { pub user_id: Uuid, pub role: String, pub scopes: Vec<String> }
+
+// Crée une clé ; la valeur complète n'est renvoyée qu'ici, seule son empreinte est stockée
+pub async fn create_key(pool: &PgPool, user_id: Uuid, name: &str, scopes: &[String], ttl_days: i64) -> Result<(ApiKeyDto, String), AppError> {
+    let key = format!("{KEY_PREFIX}{}", generate_token());
+    let prefix = key[..KEY_PREFIX.len() + VISIBLE_CHARS].to_string();
+    let row = sqlx::query("INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id, name, prefix, scopes, expires_at, last_used_at, last_used_ip, revoked_at, created_at")
+        .bind(user_id)
+        .bind(name)
+        .bind(&prefix)
+        .bind(hash_token(&key))
+        .bind(scopes)
+        .bind(Utc::now() + Duration::days(ttl_days))
+        .fetch_one(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok((to_dto(&row), key))
+}
+
+pub async fn list_keys(pool: &PgPool, user_id: Uuid) -> Result<Vec<ApiKeyDto>, AppError> {
+    let rows = sqlx::query("SELECT id, name, prefix, scopes, expires_at, last_used_at, last_used_ip, revoked_at, created_at FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(rows.iter().map(to_dto).collect())
+}
+
+pub async fn revoke_key(pool: &PgPool, user_id: Uuid, key_id: Uuid) -> Result<bool, AppError> {
+    let updated = sqlx::query("UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL")
+        .bind(key_id)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(updated.rows_affected() > 0)
+}
+
+// Résout `Authorization: Bearer wdx_...` en mettant à jour la dernière utilisation
+pub async fn authenticate(pool: &PgPool, key: &str, client: &ClientInfo) -> Result<Option<KeyPrincipal>, AppError> {
+    let row = sqlx::query("UPDATE api_keys k SET last_used_at = now(), last_used_ip = COALESCE($2, k.last_used_ip) FROM users u WHERE k.key_hash = $1 AND k.revoked_at IS NULL AND (k.expires_at IS NULL OR k.expires_at > now()) AND u.id = k.user_id RETURNING k.user_id, k.scopes, COALESCE(u.role, 'user') AS role")
+        .bind(hash_token(key))
+        .bind(client.ip.map(|ip| ip.to_string()))
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(row.map(|r| KeyPrincipal { user_id: r.get("user_id"), role: r.get("role"), scopes: r.get("scopes") }))
+}
+
+fn to_dto(r: &sqlx::postgres::PgRow) -> ApiKeyDto {
+    let id: Uuid = r.get("id");
+    ApiKeyDto { id: id.to_string(), name: r.get("name"), prefix: r.get("prefix"), scopes: r.get("scopes"), expires_at: r.get("expires_at"), last_used_at: r.get("last_used_at"), last_used_ip: r.get("last_used_ip"), revoked_at: r.get("revoked_at"), created_at: r.get("created_at") }
+}
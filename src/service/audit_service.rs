@@ -0,0 +1,21 @@
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::service::session_service::ClientInfo;
+
+// Journal d'audit des actions sensibles. Un échec d'écriture est tracé mais ne fait pas échouer la requête.
+pub async fn record(pool: &PgPool, actor_id: Option<Uuid>, action: &str, target_id: Option<Uuid>, client: &ClientInfo, details: Value) {
+    tracing::info!(actor_id = ?actor_id, action, target_id = ?target_id, ip = ?client.ip, "audit");
+    let result = sqlx::query("INSERT INTO audit_log (actor_id, action, target_id, ip, user_agent, details) VALUES ($1, $2, $3, $4, $5, $6)")
+        .bind(actor_id)
+        .bind(action)
+        .bind(target_id)
+        .bind(client.ip.map(|ip| ip.to_string()))
+        .bind(client.user_agent.as_deref())
+        .bind(details)
+        .execute(pool)
+        .await;
+    if let Err(e) = result {
+        tracing::error!(action, error = %e, "failed to write audit log");
+    }
+}
@@ -0,0 +1,207 @@
+use chrono::{Duration, Utc};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::api::extract::ROLE_ADMIN;
+use crate::service::{audit_service, email_service, session_service::{self, ClientInfo}};
+use crate::utils::{config::Config, error::AppError, jwt::create_token, token::{generate_token, hash_token}};
+
+pub struct IssuedTokens { pub access_token: String, pub refresh_token: String }
+
+// Verrouillage progressif : à partir du seuil, 1, 2, 4... minutes (plafonné à une heure) par échec supplémentaire
+const MAX_LOCKOUT_MINUTES: i32 = 60;
+
+// Ouvre une session (= une famille de refresh tokens) et émet la première paire de jetons
+pub async fn issue_tokens(pool: &PgPool, cfg: &Config, user_id: Uuid, role: &str, client: &ClientInfo) -> Result<IssuedTokens, AppError> {
+    let mut tx = pool.begin().await.map_err(|_| AppError::Internal)?;
+    let device = client.user_agent.as_deref().map(session_service::device_label);
+    let ip = client.ip.map(|ip| ip.to_string());
+    // Empreinte de connexion (appareil + IP) jamais vue, alors que le compte a déjà des sessions
+    let fingerprint = sqlx::query("SELECT EXISTS(SELECT 1 FROM sessions WHERE user_id = $1) AS has_sessions, EXISTS(SELECT 1 FROM sessions WHERE user_id = $1 AND device IS NOT DISTINCT FROM $2 AND ip IS NOT DISTINCT FROM $3) AS known")
+        .bind(user_id)
+        .bind(&device)
+        .bind(&ip)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let unknown_device = fingerprint.get::<bool, _>("has_sessions") && !fingerprint.get::<bool, _>("known");
+    // Se reconnecter pendant le délai de grâce annule la suppression du compte
+    let user = sqlx::query("UPDATE users u SET last_login_at = now(), failed_login_attempts = 0, locked_until = NULL, deletion_requested_at = NULL FROM users old WHERE u.id = $1 AND old.id = u.id RETURNING u.email, old.deletion_requested_at IS NOT NULL AS deletion_cancelled")
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let session_id = session_service::create_session(&mut *tx, user_id, client).await?;
+    let refresh_token = generate_token();
+    let expires_at = Utc::now() + Duration::days(cfg.refresh_token_ttl_days);
+    sqlx::query("INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at) VALUES ($1, $2, $3, $4)")
+        .bind(user_id)
+        .bind(session_id)
+        .bind(hash_token(&refresh_token))
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+    let email: String = user.get("email");
+    if user.get::<bool, _>("deletion_cancelled") {
+        let body = "Bonjour,\n\nVous vous êtes reconnecté : la suppression de votre compte WindevExpert a été annulée. Les abonnements résiliés ne sont pas réactivés automatiquement.".to_string();
+        email_service::spawn_send(cfg.smtp_config.clone(), email.clone(), "Suppression de votre compte annulée".to_string(), body);
+    }
+    if unknown_device {
+        let body = format!("Bonjour,\n\nUne nouvelle connexion à votre compte WindevExpert a eu lieu le {} (UTC).\n\nAppareil : {}\nAdresse IP : {}\n\nSi ce n'était pas vous, changez votre mot de passe et déconnectez toutes vos sessions depuis votre profil.",
+            Utc::now().format("%d/%m/%Y à %H:%M"), device.as_deref().unwrap_or("inconnu"), ip.as_deref().unwrap_or("inconnue"));
+        email_service::spawn_send(cfg.smtp_config.clone(), email, "Nouvelle connexion à votre compte".to_string(), body);
+    }
+    if role == ROLE_ADMIN {
+        audit_service::record(pool, Some(user_id), "admin.login", Some(user_id), client, serde_json::json!({ "session_id": session_id })).await;
+    }
+    let access_token = create_token(&user_id.to_string(), role, Some(session_id), &cfg.jwt_keys, cfg.access_token_ttl_minutes)?;
+    Ok(IssuedTokens { access_token, refresh_token })
+}
+
+// Échange un refresh token contre une nouvelle paire. Un jeton déjà utilisé (rejeu)
+// révoque toute sa famille : le voleur comme l'utilisateur légitime devront se reconnecter.
+pub async fn rotate_refresh_token(pool: &PgPool, cfg: &Config, presented: &str, client: &ClientInfo) -> Result<IssuedTokens, AppError> {
+    let mut tx = pool.begin().await.map_err(|_| AppError::Internal)?;
+    let row = sqlx::query("SELECT rt.id, rt.user_id, rt.family_id, rt.expires_at, rt.revoked_at, s.revoked_at IS NOT NULL AS session_revoked, u.role FROM refresh_tokens rt JOIN users u ON u.id = rt.user_id LEFT JOIN sessions s ON s.id = rt.family_id WHERE rt.token_hash = $1 FOR UPDATE OF rt")
+        .bind(hash_token(presented))
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::Unauthorized) };
+
+    let id: Uuid = row.get("id");
+    let user_id: Uuid = row.get("user_id");
+    let family_id: Uuid = row.get("family_id");
+    let expires_at: chrono::DateTime<Utc> = row.get("expires_at");
+    let revoked_at: Option<chrono::DateTime<Utc>> = row.get("revoked_at");
+    let session_revoked: Option<bool> = row.get("session_revoked");
+    let role: Option<String> = row.get("role");
+
+    if session_revoked == Some(true) { return Err(AppError::Unauthorized) }
+    if revoked_at.is_some() {
+        tracing::warn!(%user_id, %family_id, "refresh token reuse detected, revoking family");
+        drop(tx);
+        session_service::revoke_session(pool, user_id, family_id).await?;
+        return Err(AppError::Unauthorized);
+    }
+    if expires_at <= Utc::now() { return Err(AppError::Unauthorized) }
+
+    let refresh_token = generate_token();
+    let new_row = sqlx::query("INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at) VALUES ($1, $2, $3, $4) RETURNING id")
+        .bind(user_id)
+        .bind(family_id)
+        .bind(hash_token(&refresh_token))
+        .bind(Utc::now() + Duration::days(cfg.refresh_token_ttl_days))
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let new_id: Uuid = new_row.get("id");
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = now(), replaced_by = $2 WHERE id = $1")
+        .bind(id)
+        .bind(new_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    session_service::touch_session(&mut *tx, family_id, client).await?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+
+    let access_token = create_token(&user_id.to_string(), role.as_deref().unwrap_or("user"), Some(family_id), &cfg.jwt_keys, cfg.access_token_ttl_minutes)?;
+    Ok(IssuedTokens { access_token, refresh_token })
+}
+
+const VERIFICATION_TOKEN_TTL_HOURS: i64 = 48;
+
+// Envoie un lien de confirmation pour l'adresse donnée : adresse du compte à l'inscription, nouvelle adresse lors d'un changement
+pub async fn send_email_verification(pool: &PgPool, cfg: &Config, user_id: Uuid, email: &str) -> Result<(), AppError> {
+    let token = generate_token();
+    sqlx::query("INSERT INTO email_verification_tokens (user_id, email, token_hash, expires_at) VALUES ($1, $2, $3, $4)")
+        .bind(user_id)
+        .bind(email)
+        .bind(hash_token(&token))
+        .bind(Utc::now() + Duration::hours(VERIFICATION_TOKEN_TTL_HOURS))
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let link = format!("{}/verify-email?token={}", cfg.frontend_url, token);
+    let body = format!("Bonjour,\n\nMerci de confirmer votre adresse email en ouvrant ce lien (valable {} heures) :\n{}\n", VERIFICATION_TOKEN_TTL_HOURS, link);
+    crate::service::email_service::spawn_send(cfg.smtp_config.clone(), email.to_string(), "Confirmez votre adresse email".to_string(), body);
+    Ok(())
+}
+
+// Bloque les actions sensibles (achat, Q&A) tant que l'adresse n'est pas confirmée, si REQUIRE_VERIFIED_EMAIL est actif
+pub async fn ensure_email_verified(pool: &PgPool, cfg: &Config, user_id: Uuid) -> Result<(), AppError> {
+    if !cfg.require_verified_email { return Ok(()) }
+    let row = sqlx::query("SELECT email_verified_at IS NOT NULL AS verified FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    match row.map(|r| r.get::<bool, _>("verified")) {
+        Some(true) => Ok(()),
+        _ => Err(AppError::Forbidden),
+    }
+}
+
+// Secondes restantes si le compte est temporairement verrouillé après trop d'échecs
+pub async fn lockout_remaining(pool: &PgPool, user_id: Uuid) -> Result<Option<u64>, AppError> {
+    let row = sqlx::query("SELECT GREATEST(EXTRACT(EPOCH FROM locked_until - now()), 0)::float8 AS remaining FROM users WHERE id = $1 AND locked_until > now()")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(row.map(|r| r.get::<f64, _>("remaining").ceil().max(1.0) as u64))
+}
+
+// Pour un utilisateur déjà connecté, qui connaît son compte : le délai peut être renvoyé
+pub async fn check_lockout(pool: &PgPool, user_id: Uuid) -> Result<(), AppError> {
+    match lockout_remaining(pool, user_id).await? {
+        Some(retry_after_secs) => Err(AppError::TooManyRequests { retry_after_secs }),
+        None => Ok(()),
+    }
+}
+
+// Durée du verrouillage : doublée à chaque échec au-delà du seuil, plafonnée (sans débordement quel que soit le compteur)
+fn lockout_minutes(attempts: i32, threshold: i32) -> Option<i32> {
+    if attempts < threshold { return None }
+    Some(2i32.saturating_pow((attempts - threshold) as u32).min(MAX_LOCKOUT_MINUTES))
+}
+
+pub async fn record_failed_login(pool: &PgPool, cfg: &Config, user_id: Uuid) -> Result<(), AppError> {
+    let row = sqlx::query("UPDATE users SET failed_login_attempts = failed_login_attempts + 1 WHERE id = $1 RETURNING failed_login_attempts")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let attempts: i32 = row.get("failed_login_attempts");
+    if let Some(minutes) = lockout_minutes(attempts, cfg.login_lockout_threshold) {
+        let row = sqlx::query("UPDATE users SET locked_until = now() + make_interval(mins => $2) WHERE id = $1 RETURNING locked_until")
+            .bind(user_id)
+            .bind(minutes)
+            .fetch_one(pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        let locked_until: Option<chrono::DateTime<Utc>> = row.get("locked_until");
+        tracing::warn!(%user_id, attempts, ?locked_until, "account locked after repeated failed logins");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockout_doubles_then_caps_without_overflow() {
+        assert_eq!(lockout_minutes(4, 5), None);
+        assert_eq!(lockout_minutes(5, 5), Some(1));
+        assert_eq!(lockout_minutes(6, 5), Some(2));
+        assert_eq!(lockout_minutes(10, 5), Some(32));
+        assert_eq!(lockout_minutes(11, 5), Some(MAX_LOCKOUT_MINUTES));
+        // Au-delà de 31 échecs, 2^n ne tient plus dans un int : le verrouillage doit rester plafonné
+        for attempts in 30..=200 {
+            assert_eq!(lockout_minutes(attempts, 5), Some(MAX_LOCKOUT_MINUTES), "attempts {attempts}");
+        }
+        assert_eq!(lockout_minutes(i32::MAX, 1), Some(MAX_LOCKOUT_MINUTES));
+    }
+}
@@ -0,0 +1,100 @@
+use std::{collections::BTreeMap, io::Cursor};
+use image::{codecs::{jpeg::JpegEncoder, webp::WebPEncoder}, imageops::FilterType, DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::utils::{config::Config, error::AppError, token::generate_token};
+
+pub const MAX_AVATAR_BYTES: usize = 5 * 1024 * 1024;
+// Côtés des miniatures carrées produites ; `DEFAULT_SIZE` est celle enregistrée dans users.avatar_url
+pub const SIZES: [u32; 4] = [64, 128, 256, 512];
+const DEFAULT_SIZE: u32 = 256;
+// Garde-fou contre les images "bombe" : dimensions et mémoire de décodage bornées
+const MAX_DIMENSION: u32 = 8000;
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Serialize)]
+pub struct AvatarVariant { pub webp: String, pub jpeg: String }
+
+#[derive(Serialize)]
+pub struct AvatarDto { pub avatar_url: String, pub variants: BTreeMap<u32, AvatarVariant> }
+
+struct Thumbnail { size: u32, webp: Vec<u8>, jpeg: Vec<u8> }
+
+// Le format est déterminé par le contenu, jamais par le nom ou le Content-Type envoyés
+fn sniff(bytes: &[u8]) -> Result<ImageFormat, AppError> {
+    match image::guess_format(bytes) {
+        Ok(format @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Gif)) => Ok(format),
+        _ => Err(AppError::field("avatar", "Unsupported image format (JPEG, PNG, WebP or GIF expected)")),
+    }
+}
+
+// Décode, redresse selon l'orientation EXIF puis réencode : les métadonnées d'origine (EXIF, GPS...) ne sont jamais recopiées
+fn render_thumbnails(bytes: &[u8], format: ImageFormat) -> Result<Vec<Thumbnail>, AppError> {
+    let invalid = || AppError::field("avatar", "The image could not be read");
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(|_| invalid())?;
+    let orientation = decoder.orientation().map_err(|_| invalid())?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|_| invalid())?;
+    image.apply_orientation(orientation);
+
+    SIZES.iter().map(|&size| {
+        // Recadrage centré puis redimensionnement au carré
+        let square = DynamicImage::ImageRgba8(image.resize_to_fill(size, size, FilterType::Lanczos3).to_rgba8());
+        let mut webp = Vec::new();
+        square.write_with_encoder(WebPEncoder::new_lossless(&mut webp)).map_err(|_| AppError::Internal)?;
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(square.to_rgb8()).write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)).map_err(|_| AppError::Internal)?;
+        Ok(Thumbnail { size, webp, jpeg })
+    }).collect()
+}
+
+// Remplace l'avatar : les miniatures sont stockées sous une clé nouvelle à chaque envoi (pas de cache périmé),
+// puis les fichiers de l'avatar précédent sont supprimés
+pub async fn replace_avatar(pool: &PgPool, cfg: &Config, user_id: Uuid, bytes: Vec<u8>) -> Result<AvatarDto, AppError> {
+    if bytes.is_empty() { return Err(AppError::field("avatar", "The file is empty")) }
+    if bytes.len() > MAX_AVATAR_BYTES { return Err(AppError::field("avatar", format!("The file must not exceed {} MB", MAX_AVATAR_BYTES / 1024 / 1024))) }
+    let format = sniff(&bytes)?;
+    let thumbnails = tokio::task::spawn_blocking(move || render_thumbnails(&bytes, format)).await.map_err(|_| AppError::Internal)??;
+
+    let prefix = format!("avatars/{}/{}", user_id, &generate_token()[..16]);
+    let mut variants = BTreeMap::new();
+    for thumb in thumbnails {
+        let webp = cfg.storage.put(&format!("{prefix}/{}.webp", thumb.size), thumb.webp, "image/webp").await?;
+        let jpeg = cfg.storage.put(&format!("{prefix}/{}.jpg", thumb.size), thumb.jpeg, "image/jpeg").await?;
+        variants.insert(thumb.size, AvatarVariant { webp, jpeg });
+    }
+    let avatar_url = variants[&DEFAULT_SIZE].webp.clone();
+
+    let previous = sqlx::query("UPDATE users u SET avatar_url = $1, updated_at = now() FROM users old WHERE u.id = $2 AND old.id = u.id RETURNING old.avatar_url")
+        .bind(&avatar_url)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .and_then(|r| r.get::<Option<String>, _>("avatar_url"));
+    if let Some(previous) = previous {
+        remove_avatar_files(cfg, user_id, &previous).await;
+    }
+    Ok(AvatarDto { avatar_url, variants })
+}
+
+pub async fn remove_avatar_files(cfg: &Config, user_id: Uuid, url: &str) {
+    let Some(key) = cfg.storage.key_for_url(url) else { return };
+    // Seuls les fichiers produits ici (avatars/<user>/<version>/...) sont supprimés
+    let Some((dir, _)) = key.rsplit_once('/') else { return };
+    if !dir.starts_with(&format!("avatars/{user_id}/")) { return }
+    for size in SIZES {
+        for ext in ["webp", "jpg"] {
+            if let Err(e) = cfg.storage.delete(&format!("{dir}/{size}.{ext}")).await {
+                tracing::warn!(error = %e, %user_id, "cannot delete previous avatar");
+            }
+        }
+    }
+}
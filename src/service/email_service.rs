@@ -1,11 +1,64 @@
-use lettre::{Message, SmtpTransport, Transport};
+use lettre::{message::header::{HeaderName, HeaderValue}, Message, SmtpTransport, Transport};
 use anyhow::Result;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::service::notification_service::{self, Category};
+use crate::utils::{config::Config, error::AppError};
 
-#[allow(dead_code)]
-pub async fn send_plain(to: &str, subject: &str, body: &str, smtp_config: &str) -> Result<()> {
-    let mail = Message::builder().from("WindevExpert <no-reply@windevexpert>".parse().unwrap()).to(to.parse().unwrap()).subject(subject).body(body.to_string()).unwrap();
-    let mailer = SmtpTransport::relay(smtp_config).unwrap().build();
-    mailer.send(&mail).map(|_| ())?;
+pub async fn send_plain(to: &str, subject: &str, body: &str, headers: Vec<HeaderValue>, smtp_config: &str) -> Result<()> {
+    let mut builder = Message::builder().from("WindevExpert <no-reply@windevexpert>".parse()?).to(to.parse()?).subject(subject);
+    for header in headers { builder = builder.raw_header(header) }
+    let mail = builder.body(body.to_string())?;
+    let mailer = SmtpTransport::relay(smtp_config)?.build();
+    // Le transport SMTP de lettre est bloquant : on l'exécute hors du runtime async
+    tokio::task::spawn_blocking(move || mailer.send(&mail).map(|_| ())).await??;
     Ok(())
 }
 
+// Envoi en tâche de fond : la réponse HTTP ne dépend ni de la latence SMTP ni de son succès
+pub fn spawn_send(smtp_config: Option<String>, to: String, subject: String, body: String) {
+    spawn_with_headers(smtp_config, to, subject, body, Vec::new());
+}
+
+fn spawn_with_headers(smtp_config: Option<String>, to: String, subject: String, body: String, headers: Vec<HeaderValue>) {
+    let Some(smtp) = smtp_config else {
+        tracing::warn!(%to, %subject, "SMTP_CONFIG not set, email not sent");
+        return;
+    };
+    tokio::spawn(async move {
+        if let Err(e) = send_plain(&to, &subject, &body, headers, &smtp).await {
+            tracing::error!(%to, %subject, error = %e, "failed to send email");
+        }
+    });
+}
+
+// Emails non essentiels (annonces, réponses, offres, résumés) : envoyés seulement si l'utilisateur accepte la catégorie,
+// avec désinscription en un clic (RFC 8058) dans les en-têtes et un lien en pied de message.
+// Les emails de compte et de sécurité passent par `spawn_send`.
+#[allow(dead_code)]
+pub async fn spawn_send_notification(pool: &PgPool, cfg: &Config, user_id: Uuid, category: Category, subject: String, body: String) -> Result<(), AppError> {
+    let row = sqlx::query("SELECT email FROM users WHERE id = $1 AND deleted_at IS NULL AND deletion_requested_at IS NULL")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Ok(()) };
+    if !notification_service::get_preferences(pool, user_id).await?.allows(category) { return Ok(()) }
+
+    let footer = format!("Vous recevez cet email car la catégorie « {} » est activée dans vos préférences de notification.", category.label());
+    // Sans UNSUBSCRIBE_SECRET, pas de lien en un clic : seules les préférences du profil permettent de se désinscrire
+    let (headers, body) = match notification_service::unsubscribe_token(cfg, user_id, category) {
+        Some(token) => {
+            let one_click = format!("{}/api/profile/notifications/unsubscribe?token={}", cfg.api_url.trim_end_matches('/'), token);
+            let page = format!("{}/unsubscribe?token={}", cfg.frontend_url, token);
+            let headers = vec![
+                HeaderValue::new(HeaderName::new_from_ascii_str("List-Unsubscribe"), format!("<{one_click}>")),
+                HeaderValue::new(HeaderName::new_from_ascii_str("List-Unsubscribe-Post"), "List-Unsubscribe=One-Click".to_string()),
+            ];
+            (headers, format!("{body}\n\n--\n{footer}\nSe désinscrire : {page}"))
+        }
+        None => (Vec::new(), format!("{body}\n\n--\n{footer}\nGérer vos préférences : {}/profile", cfg.frontend_url)),
+    };
+    spawn_with_headers(cfg.smtp_config.clone(), row.get("email"), subject, body, headers);
+    Ok(())
+}
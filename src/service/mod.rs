@@ -1,3 +1,13 @@
+pub mod admin_service;
+pub mod api_key_service;
+pub mod audit_service;
+pub mod avatar_service;
+pub mod auth_service;
 pub mod email_service;
+pub mod notification_service;
+pub mod oidc_service;
+pub mod privacy_service;
+pub mod session_service;
+pub mod settings_service;
+pub mod two_factor_service;
 pub mod video_service;
-
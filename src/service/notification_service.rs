@@ -0,0 +1,158 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::utils::{config::Config, error::AppError};
+
+// Catégories d'emails non essentiels ; les emails liés au compte et à la sécurité ne sont jamais désactivables
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category { CourseAnnouncements, QaReplies, Marketing, Digests }
+
+impl Category {
+    const ALL: [Category; 4] = [Category::CourseAnnouncements, Category::QaReplies, Category::Marketing, Category::Digests];
+
+    // Colonne de notification_preferences, reprise telle quelle dans les jetons de désinscription
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Category::CourseAnnouncements => "course_announcements",
+            Category::QaReplies => "qa_replies",
+            Category::Marketing => "marketing",
+            Category::Digests => "digests",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Category::CourseAnnouncements => "Annonces des formations",
+            Category::QaReplies => "Réponses à vos questions",
+            Category::Marketing => "Offres et nouveautés",
+            Category::Digests => "Résumés périodiques",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Category> { Category::ALL.into_iter().find(|c| c.as_str() == s) }
+}
+
+#[derive(Serialize)]
+pub struct Preferences { pub course_announcements: bool, pub qa_replies: bool, pub marketing: bool, pub digests: bool }
+
+// Mêmes valeurs que les DEFAULT de la migration : le marketing demande un consentement explicite
+impl Default for Preferences {
+    fn default() -> Self { Preferences { course_announcements: true, qa_replies: true, marketing: false, digests: true } }
+}
+
+impl Preferences {
+    pub fn allows(&self, category: Category) -> bool {
+        match category {
+            Category::CourseAnnouncements => self.course_announcements,
+            Category::QaReplies => self.qa_replies,
+            Category::Marketing => self.marketing,
+            Category::Digests => self.digests,
+        }
+    }
+}
+
+// Champ absent : inchangé
+#[derive(Deserialize)]
+pub struct PreferencesUpdate { pub course_announcements: Option<bool>, pub qa_replies: Option<bool>, pub marketing: Option<bool>, pub digests: Option<bool> }
+
+pub async fn get_preferences(pool: &PgPool, user_id: Uuid) -> Result<Preferences, AppError> {
+    let row = sqlx::query("SELECT course_announcements, qa_replies, marketing, digests FROM notification_preferences WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(row.map(|r| Preferences { course_announcements: r.get("course_announcements"), qa_replies: r.get("qa_replies"), marketing: r.get("marketing"), digests: r.get("digests") }).unwrap_or_default())
+}
+
+pub async fn update_preferences(pool: &PgPool, user_id: Uuid, update: &PreferencesUpdate) -> Result<Preferences, AppError> {
+    sqlx::query("INSERT INTO notification_preferences (user_id) VALUES ($1) ON CONFLICT (user_id) DO NOTHING").bind(user_id).execute(pool).await.map_err(|_| AppError::Internal)?;
+    sqlx::query("UPDATE notification_preferences SET course_announcements = COALESCE($1, course_announcements), qa_replies = COALESCE($2, qa_replies), marketing = COALESCE($3, marketing), digests = COALESCE($4, digests), updated_at = now() WHERE user_id = $5")
+        .bind(update.course_announcements)
+        .bind(update.qa_replies)
+        .bind(update.marketing)
+        .bind(update.digests)
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    get_preferences(pool, user_id).await
+}
+
+// Jeton "<user_id>.<catégorie>.<signature>" sans expiration : un lien de désinscription doit rester valable
+// aussi longtemps que l'email qui le contient. None sans UNSUBSCRIBE_SECRET.
+pub fn unsubscribe_token(cfg: &Config, user_id: Uuid, category: Category) -> Option<String> {
+    Some(sign_unsubscribe(cfg.unsubscribe_secret.as_deref()?, user_id, category))
+}
+
+fn sign_unsubscribe(secret: &str, user_id: Uuid, category: Category) -> String {
+    let payload = format!("{user_id}.{}", category.as_str());
+    let signature = URL_SAFE_NO_PAD.encode(unsubscribe_mac(secret, &payload).finalize().into_bytes());
+    format!("{payload}.{signature}")
+}
+
+fn unsubscribe_mac(secret: &str, payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    // Préfixe propre à cet usage : la signature ne peut servir ailleurs
+    mac.update(b"unsubscribe:");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+fn verify_unsubscribe_token(secret: &str, token: &str) -> Option<(Uuid, Category)> {
+    let (payload, signature) = token.rsplit_once('.')?;
+    unsubscribe_mac(secret, payload).verify_slice(&URL_SAFE_NO_PAD.decode(signature).ok()?).ok()?;
+    let (user_id, category) = payload.split_once('.')?;
+    Some((user_id.parse().ok()?, Category::parse(category)?))
+}
+
+// Désinscription en un clic : seule la catégorie de l'email d'origine est désactivée
+pub async fn unsubscribe(pool: &PgPool, cfg: &Config, token: &str) -> Result<Category, AppError> {
+    let secret = cfg.unsubscribe_secret.as_deref().ok_or(AppError::BadRequest)?;
+    let (user_id, category) = verify_unsubscribe_token(secret, token).ok_or(AppError::BadRequest)?;
+    let column = category.as_str();
+    sqlx::query(&format!("INSERT INTO notification_preferences (user_id, {column}) SELECT id, false FROM users WHERE id = $1 ON CONFLICT (user_id) DO UPDATE SET {column} = false, updated_at = now()"))
+        .bind(user_id)
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(category)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-unsubscribe-secret-0123456789abcdef";
+
+    #[test]
+    fn unsubscribe_token_round_trip() {
+        let user_id = Uuid::new_v4();
+        for category in Category::ALL {
+            let token = sign_unsubscribe(SECRET, user_id, category);
+            assert!(token.starts_with(&format!("{user_id}.{}.", category.as_str())));
+            assert!(verify_unsubscribe_token(SECRET, &token) == Some((user_id, category)));
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_or_foreign_tokens() {
+        let user_id = Uuid::new_v4();
+        let token = sign_unsubscribe(SECRET, user_id, Category::Marketing);
+        let (payload, signature) = token.rsplit_once('.').unwrap();
+
+        // Autre catégorie ou autre utilisateur avec la signature d'origine
+        assert!(verify_unsubscribe_token(SECRET, &format!("{user_id}.digests.{signature}")).is_none());
+        assert!(verify_unsubscribe_token(SECRET, &format!("{}.marketing.{signature}", Uuid::new_v4())).is_none());
+        // Clé différente, signature tronquée ou absente, catégorie inconnue
+        assert!(verify_unsubscribe_token("another-secret-0123456789abcdefghij", &token).is_none());
+        assert!(verify_unsubscribe_token(SECRET, &format!("{payload}.{}", &signature[..10])).is_none());
+        assert!(verify_unsubscribe_token(SECRET, payload).is_none());
+        let unknown = format!("{user_id}.billing");
+        let forged = URL_SAFE_NO_PAD.encode(unsubscribe_mac(SECRET, &unknown).finalize().into_bytes());
+        assert!(verify_unsubscribe_token(SECRET, &format!("{unknown}.{forged}")).is_none());
+    }
+}
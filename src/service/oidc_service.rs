@@ -0,0 +1,432 @@
+use std::{collections::HashMap, sync::{LazyLock, Mutex}, time::{Duration, Instant}};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::service::session_service;
+use crate::utils::{config::{Config, OAuthKind, OAuthProvider}, error::AppError, password::hash_password, token::{generate_token, hash_token}};
+
+const STATE_TTL_MINUTES: i32 = 10;
+const DISCOVERY_TTL: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Deserialize)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+// Documents de découverte et JWKS mis en cache par issuer
+type DiscoveryCache = HashMap<String, (Discovery, JwkSet, Instant)>;
+static DISCOVERY_CACHE: LazyLock<Mutex<DiscoveryCache>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Identité renvoyée par le fournisseur après échange du code
+pub struct ExternalIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+pub struct LinkedUser { pub id: Uuid, pub role: String, pub totp_enabled: bool }
+
+#[derive(Deserialize)]
+struct TokenResponse { access_token: String, id_token: Option<String> }
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: Option<serde_json::Value>,
+    name: Option<String>,
+}
+
+pub fn find_provider<'a>(cfg: &'a Config, name: &str) -> Result<&'a OAuthProvider, AppError> {
+    cfg.oauth_providers.iter().find(|p| p.name == name).ok_or(AppError::NotFound)
+}
+
+pub fn redirect_uri(cfg: &Config, provider: &OAuthProvider) -> String {
+    format!("{}/api/auth/oauth/{}/callback", cfg.api_url.trim_end_matches('/'), provider.name)
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder().timeout(Duration::from_secs(10)).build().unwrap_or_default()
+}
+
+async fn discover(provider: &OAuthProvider) -> Result<(Discovery, JwkSet), AppError> {
+    if let Some((discovery, jwks, fetched_at)) = DISCOVERY_CACHE.lock().unwrap().get(&provider.issuer) {
+        if fetched_at.elapsed() < DISCOVERY_TTL { return Ok((discovery.clone(), jwks.clone())) }
+    }
+    let url = format!("{}/.well-known/openid-configuration", provider.issuer);
+    let discovery: Discovery = http_client().get(&url).send().await.and_then(|r| r.error_for_status()).map_err(|_| AppError::Internal)?.json().await.map_err(|_| AppError::Internal)?;
+    let jwks = fetch_jwks(provider, &discovery).await?;
+    Ok((discovery, jwks))
+}
+
+async fn fetch_jwks(provider: &OAuthProvider, discovery: &Discovery) -> Result<JwkSet, AppError> {
+    let jwks: JwkSet = http_client().get(&discovery.jwks_uri).send().await.and_then(|r| r.error_for_status()).map_err(|_| AppError::Internal)?.json().await.map_err(|_| AppError::Internal)?;
+    DISCOVERY_CACHE.lock().unwrap().insert(provider.issuer.clone(), (discovery.clone(), jwks.clone(), Instant::now()));
+    Ok(jwks)
+}
+
+// kid absent du JWKS en cache : le fournisseur a pu faire tourner ses clés, le JWKS est rechargé sans attendre l'expiration
+async fn signing_keys(provider: &OAuthProvider, discovery: &Discovery, jwks: JwkSet, id_token: &str) -> Result<JwkSet, AppError> {
+    let kid = jsonwebtoken::decode_header(id_token).map_err(|_| AppError::Unauthorized)?.kid;
+    match kid {
+        Some(kid) if jwks.find(&kid).is_none() => fetch_jwks(provider, discovery).await,
+        _ => Ok(jwks),
+    }
+}
+
+fn endpoints(provider: &OAuthProvider, discovery: Option<&Discovery>) -> (String, String) {
+    match (&provider.kind, discovery) {
+        (OAuthKind::Oidc, Some(d)) => (d.authorization_endpoint.clone(), d.token_endpoint.clone()),
+        _ => (format!("{}/login/oauth/authorize", provider.issuer), format!("{}/login/oauth/access_token", provider.issuer)),
+    }
+}
+
+// Étape 1 : URL d'autorisation (code + PKCE S256). state, nonce et code_verifier restent côté serveur.
+pub async fn authorization_url(pool: &PgPool, cfg: &Config, provider: &OAuthProvider) -> Result<String, AppError> {
+    let discovery = match provider.kind { OAuthKind::Oidc => Some(discover(provider).await?.0), OAuthKind::GitHub => None };
+    let (authorize_endpoint, _) = endpoints(provider, discovery.as_ref());
+    let state = generate_token();
+    let nonce = generate_token();
+    let code_verifier = generate_token();
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+    // Les states abandonnés (callback jamais appelé) sont purgés au fil des nouvelles autorisations
+    sqlx::query("DELETE FROM oauth_states WHERE expires_at < now()").execute(pool).await.map_err(|_| AppError::Internal)?;
+    sqlx::query("INSERT INTO oauth_states (state_hash, provider, code_verifier, nonce, expires_at) VALUES ($1, $2, $3, $4, now() + make_interval(mins => $5))")
+        .bind(hash_token(&state))
+        .bind(&provider.name)
+        .bind(&code_verifier)
+        .bind(&nonce)
+        .bind(STATE_TTL_MINUTES)
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+    let scope = match provider.kind { OAuthKind::Oidc => "openid email profile", OAuthKind::GitHub => "read:user user:email" };
+    let params = [
+        ("response_type", "code"),
+        ("client_id", provider.client_id.as_str()),
+        ("redirect_uri", &redirect_uri(cfg, provider)),
+        ("scope", scope),
+        ("state", &state),
+        ("nonce", &nonce),
+        ("code_challenge", &code_challenge),
+        ("code_challenge_method", "S256"),
+    ];
+    let query: Vec<String> = params.iter().map(|(k, v)| format!("{k}={}", urlencoding::encode(v))).collect();
+    let separator = if authorize_endpoint.contains('?') { '&' } else { '?' };
+    Ok(format!("{authorize_endpoint}{separator}{}", query.join("&")))
+}
+
+// Étape 2 : consomme le state (usage unique), échange le code et valide l'identité renvoyée
+pub async fn exchange_code(pool: &PgPool, cfg: &Config, provider: &OAuthProvider, code: &str, state: &str) -> Result<ExternalIdentity, AppError> {
+    let row = sqlx::query("DELETE FROM oauth_states WHERE state_hash = $1 AND provider = $2 AND expires_at > now() RETURNING code_verifier, nonce")
+        .bind(hash_token(state))
+        .bind(&provider.name)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Err(AppError::BadRequest) };
+    let code_verifier: String = row.get("code_verifier");
+    let nonce: String = row.get("nonce");
+
+    let discovery = match provider.kind { OAuthKind::Oidc => Some(discover(provider).await?), OAuthKind::GitHub => None };
+    let (_, token_endpoint) = endpoints(provider, discovery.as_ref().map(|(d, _)| d));
+    let redirect = redirect_uri(cfg, provider);
+    let form = [
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect.as_str()),
+        ("client_id", provider.client_id.as_str()),
+        ("client_secret", provider.client_secret.as_str()),
+        ("code_verifier", code_verifier.as_str()),
+    ];
+    let tokens: TokenResponse = http_client().post(&token_endpoint).header(reqwest::header::ACCEPT, "application/json").form(&form).send().await
+        .and_then(|r| r.error_for_status())
+        .map_err(|_| AppError::Unauthorized)?
+        .json().await
+        .map_err(|_| AppError::Unauthorized)?;
+
+    match discovery {
+        Some((discovery, jwks)) => {
+            let id_token = tokens.id_token.ok_or(AppError::Unauthorized)?;
+            let jwks = signing_keys(provider, &discovery, jwks, &id_token).await?;
+            verify_id_token(provider, &discovery, &jwks, &id_token, &nonce)
+        }
+        None => github_identity(provider, &tokens.access_token).await,
+    }
+}
+
+fn verify_id_token(provider: &OAuthProvider, discovery: &Discovery, jwks: &JwkSet, id_token: &str, nonce: &str) -> Result<ExternalIdentity, AppError> {
+    let header = jsonwebtoken::decode_header(id_token).map_err(|_| AppError::Unauthorized)?;
+    // Seules les signatures asymétriques publiées dans le JWKS du fournisseur sont acceptées
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) { return Err(AppError::Unauthorized) }
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None => jwks.keys.first(),
+    }.ok_or(AppError::Unauthorized)?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|_| AppError::Unauthorized)?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&provider.client_id]);
+    validation.set_issuer(&[&discovery.issuer]);
+    let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation).map_err(|_| AppError::Unauthorized)?.claims;
+    if claims.nonce.as_deref() != Some(nonce) { return Err(AppError::Unauthorized) }
+    // Certains fournisseurs encodent email_verified en chaîne ("true")
+    let email_verified = match claims.email_verified {
+        Some(serde_json::Value::Bool(b)) => b,
+        Some(serde_json::Value::String(s)) => s == "true",
+        _ => false,
+    };
+    Ok(ExternalIdentity { provider: provider.name.clone(), subject: claims.sub, email: claims.email.map(|e| e.trim().to_lowercase()), email_verified, name: claims.name })
+}
+
+#[derive(Deserialize)]
+struct GitHubUser { id: i64, name: Option<String>, login: String }
+
+#[derive(Deserialize)]
+struct GitHubEmail { email: String, primary: bool, verified: bool }
+
+async fn github_identity(provider: &OAuthProvider, access_token: &str) -> Result<ExternalIdentity, AppError> {
+    // L'API est hébergée sur api.github.com, ou sous /api/v3 pour GitHub Enterprise
+    let api = if provider.issuer == "https://github.com" { "https://api.github.com".to_string() } else { format!("{}/api/v3", provider.issuer) };
+    let client = http_client();
+    let get = |path: &str| client.get(format!("{api}{path}")).bearer_auth(access_token).header(reqwest::header::USER_AGENT, "windevexpert").header(reqwest::header::ACCEPT, "application/vnd.github+json").send();
+    let user: GitHubUser = get("/user").await.and_then(|r| r.error_for_status()).map_err(|_| AppError::Unauthorized)?.json().await.map_err(|_| AppError::Unauthorized)?;
+    let emails: Vec<GitHubEmail> = get("/user/emails").await.and_then(|r| r.error_for_status()).map_err(|_| AppError::Unauthorized)?.json().await.map_err(|_| AppError::Unauthorized)?;
+    let primary = emails.into_iter().find(|e| e.primary);
+    Ok(ExternalIdentity {
+        provider: provider.name.clone(),
+        subject: user.id.to_string(),
+        email_verified: primary.as_ref().is_some_and(|e| e.verified),
+        email: primary.map(|e| e.email.trim().to_lowercase()),
+        name: user.name.or(Some(user.login)),
+    })
+}
+
+// Retrouve l'utilisateur lié à l'identité externe ; à défaut, lie un compte existant ayant la
+// même adresse (uniquement si le fournisseur l'a vérifiée) ou crée un nouveau compte.
+pub async fn link_or_create_user(pool: &PgPool, identity: &ExternalIdentity) -> Result<LinkedUser, AppError> {
+    let mut tx = pool.begin().await.map_err(|_| AppError::Internal)?;
+    let existing = sqlx::query("SELECT u.id, u.role, u.totp_enabled_at IS NOT NULL AS totp_enabled FROM provider_identities pi JOIN users u ON u.id = pi.user_id WHERE pi.provider = $1 AND pi.subject = $2")
+        .bind(&identity.provider)
+        .bind(&identity.subject)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+    let user = match existing {
+        Some(row) => row,
+        None => {
+            let email = identity.email.as_deref().ok_or(AppError::BadRequest)?;
+            let by_email = sqlx::query("SELECT id, role, totp_enabled_at IS NOT NULL AS totp_enabled, email_verified_at IS NOT NULL AS email_verified FROM users WHERE email = $1")
+                .bind(email)
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(|_| AppError::Internal)?;
+            let row = match by_email {
+                // Adresse non vérifiée par le fournisseur : on ne rattache pas un compte existant
+                Some(_) if !identity.email_verified => return Err(AppError::Conflict),
+                Some(row) if row.get("email_verified") => row,
+                // Compte local jamais confirmé : il a pu être créé par un tiers avec l'adresse de la victime.
+                // Le fournisseur prouve la possession de l'adresse ; tout accès mis en place par ce tiers est retiré.
+                Some(row) => {
+                    let user_id: Uuid = row.get("id");
+                    sqlx::query("UPDATE users SET password_hash = $2, email_verified_at = now(), totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL, failed_login_attempts = 0, locked_until = NULL, updated_at = now() WHERE id = $1")
+                        .bind(user_id)
+                        .bind(hash_password(&generate_token())?)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|_| AppError::Internal)?;
+                    for query in [
+                        "DELETE FROM recovery_codes WHERE user_id = $1",
+                        "UPDATE api_keys SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL",
+                        "UPDATE password_reset_tokens SET used_at = now() WHERE user_id = $1 AND used_at IS NULL",
+                        "UPDATE email_verification_tokens SET used_at = now() WHERE user_id = $1 AND used_at IS NULL",
+                    ] {
+                        sqlx::query(query).bind(user_id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+                    }
+                    session_service::revoke_all_sessions(&mut *tx, user_id, None).await?;
+                    tracing::warn!(%user_id, provider = %identity.provider, "unverified local account taken over by verified external identity; credentials reset");
+                    sqlx::query("SELECT id, role, false AS totp_enabled FROM users WHERE id = $1").bind(user_id).fetch_one(&mut *tx).await.map_err(|_| AppError::Internal)?
+                }
+                None => {
+                    // Mot de passe aléatoire jamais communiqué : connexion par le fournisseur ou après reset
+                    let password_hash = hash_password(&generate_token())?;
+                    sqlx::query("INSERT INTO users (email, password_hash, role, full_name, email_verified_at) VALUES ($1, $2, 'user', $3, CASE WHEN $4 THEN now() END) RETURNING id, role, false AS totp_enabled")
+                        .bind(email)
+                        .bind(&password_hash)
+                        .bind(identity.name.as_deref())
+                        .bind(identity.email_verified)
+                        .fetch_one(&mut *tx)
+                        .await
+                        .map_err(|_| AppError::Internal)?
+                }
+            };
+            let user_id: Uuid = row.get("id");
+            sqlx::query("INSERT INTO provider_identities (user_id, provider, subject, email) VALUES ($1, $2, $3, $4)")
+                .bind(user_id)
+                .bind(&identity.provider)
+                .bind(&identity.subject)
+                .bind(email)
+                .execute(&mut *tx)
+                .await
+                .map_err(|_| AppError::Internal)?;
+            tracing::info!(%user_id, provider = %identity.provider, "external identity linked");
+            row
+        }
+    };
+    let id: Uuid = user.get("id");
+    sqlx::query("UPDATE provider_identities SET last_login_at = now() WHERE provider = $1 AND subject = $2").bind(&identity.provider).bind(&identity.subject).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+    let role: Option<String> = user.get("role");
+    Ok(LinkedUser { id, role: role.unwrap_or_else(|| "user".to_string()), totp_enabled: user.get("totp_enabled") })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Json, Router};
+    use jsonwebtoken::{EncodingKey, Header};
+    use rsa::{pkcs1::EncodeRsaPrivateKey, traits::PublicKeyParts, RsaPrivateKey};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    const CLIENT_ID: &str = "windevexpert-test";
+    const KID: &str = "test-key";
+    const NONCE: &str = "test-nonce";
+
+    // Une seule clé RSA pour tous les tests : la génération est lente sans optimisations
+    static SIGNING_KEY: LazyLock<RsaPrivateKey> = LazyLock::new(|| RsaPrivateKey::new(&mut rand::thread_rng(), 2048).unwrap());
+
+    // Fournisseur OIDC local sur un port éphémère : document de découverte et JWKS
+    struct MockProvider { provider: OAuthProvider, jwks: Arc<Mutex<Value>> }
+
+    async fn mock_provider() -> MockProvider {
+        let key = &*SIGNING_KEY;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let discovery = json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{issuer}/authorize"),
+            "token_endpoint": format!("{issuer}/token"),
+            "jwks_uri": format!("{issuer}/jwks"),
+        });
+        let jwks = json!({ "keys": [{ "kty": "RSA", "kid": KID, "alg": "RS256", "use": "sig", "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()), "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()) }] });
+        let served = Arc::new(Mutex::new(jwks));
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(Json(discovery)))
+            .route("/jwks", get({
+                let served = served.clone();
+                move || async move { Json(served.lock().unwrap().clone()) }
+            }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        MockProvider { provider: OAuthProvider { name: "oidc".to_string(), kind: OAuthKind::Oidc, issuer, client_id: CLIENT_ID.to_string(), client_secret: String::new() }, jwks: served }
+    }
+
+    fn claims(provider: &OAuthProvider) -> Value {
+        json!({
+            "iss": provider.issuer,
+            "aud": CLIENT_ID,
+            "sub": "subject-42",
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "nonce": NONCE,
+            "email": " Jane.Doe@Example.COM ",
+            "email_verified": "true",
+            "name": "Jane Doe",
+        })
+    }
+
+    fn sign(claims: &Value, kid: Option<&str>) -> String {
+        let header = Header { kid: kid.map(str::to_string), ..Header::new(Algorithm::RS256) };
+        let key = EncodingKey::from_rsa_der(SIGNING_KEY.to_pkcs1_der().unwrap().as_bytes());
+        jsonwebtoken::encode(&header, claims, &key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn discovers_provider_and_accepts_valid_id_token() {
+        let provider = mock_provider().await.provider;
+        let (discovery, jwks) = discover(&provider).await.expect("discovery");
+        assert_eq!(discovery.issuer, provider.issuer);
+        assert_eq!(endpoints(&provider, Some(&discovery)), (format!("{}/authorize", provider.issuer), format!("{}/token", provider.issuer)));
+        assert!(jwks.find(KID).is_some());
+
+        let identity = verify_id_token(&provider, &discovery, &jwks, &sign(&claims(&provider), Some(KID)), NONCE).expect("valid id_token");
+        assert_eq!(identity.provider, "oidc");
+        assert_eq!(identity.subject, "subject-42");
+        assert_eq!(identity.email.as_deref(), Some("jane.doe@example.com"));
+        assert!(identity.email_verified);
+        assert_eq!(identity.name.as_deref(), Some("Jane Doe"));
+
+        // Sans kid, la première clé du JWKS est utilisée
+        assert!(verify_id_token(&provider, &discovery, &jwks, &sign(&claims(&provider), None), NONCE).is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_id_tokens() {
+        let provider = mock_provider().await.provider;
+        let (discovery, jwks) = discover(&provider).await.expect("discovery");
+        let rejected = |token: String| verify_id_token(&provider, &discovery, &jwks, &token, NONCE).is_err();
+
+        let with = |field: &str, value: Value| {
+            let mut c = claims(&provider);
+            c[field] = value;
+            sign(&c, Some(KID))
+        };
+        assert!(rejected(with("nonce", json!("other-nonce"))));
+        assert!(rejected(with("aud", json!("other-client"))));
+        assert!(rejected(with("iss", json!("https://evil.example"))));
+        assert!(rejected(with("exp", json!(chrono::Utc::now().timestamp() - 3600))));
+        assert!(rejected(sign(&claims(&provider), Some("unknown-kid"))));
+
+        // Charge utile modifiée après signature
+        let token = sign(&claims(&provider), Some(KID));
+        let mut parts: Vec<&str> = token.split('.').collect();
+        let mut forged = claims(&provider);
+        forged["sub"] = json!("admin");
+        let forged_payload = URL_SAFE_NO_PAD.encode(forged.to_string());
+        parts[1] = &forged_payload;
+        assert!(rejected(parts.join(".")));
+
+        // Signature symétrique refusée, même avec un secret connu
+        let hs256 = jsonwebtoken::encode(&Header { kid: Some(KID.to_string()), ..Header::new(Algorithm::HS256) }, &claims(&provider), &EncodingKey::from_secret(b"secret")).unwrap();
+        assert!(rejected(hs256));
+
+        // email_verified absent ou faux : l'adresse n'est pas considérée comme vérifiée
+        let identity = verify_id_token(&provider, &discovery, &jwks, &with("email_verified", json!(false)), NONCE).unwrap();
+        assert!(!identity.email_verified);
+    }
+
+    #[tokio::test]
+    async fn discovery_fails_when_provider_is_unreachable() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let provider = OAuthProvider { name: "oidc".to_string(), kind: OAuthKind::Oidc, issuer, client_id: CLIENT_ID.to_string(), client_secret: String::new() };
+        assert!(matches!(discover(&provider).await, Err(AppError::Internal)));
+    }
+
+    #[tokio::test]
+    async fn refetches_jwks_when_kid_is_unknown() {
+        let mock = mock_provider().await;
+        let provider = &mock.provider;
+        let (discovery, jwks) = discover(provider).await.expect("discovery");
+
+        // Rotation de clés chez le fournisseur après la mise en cache
+        mock.jwks.lock().unwrap()["keys"][0]["kid"] = json!("rotated-key");
+        let token = sign(&claims(provider), Some("rotated-key"));
+        assert!(verify_id_token(provider, &discovery, &jwks, &token, NONCE).is_err());
+        let jwks = signing_keys(provider, &discovery, jwks, &token).await.expect("refetched jwks");
+        assert!(verify_id_token(provider, &discovery, &jwks, &token, NONCE).is_ok());
+        // Le cache contient désormais les nouvelles clés
+        assert!(discover(provider).await.unwrap().1.find("rotated-key").is_some());
+    }
+}
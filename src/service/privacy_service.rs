@@ -0,0 +1,181 @@
+use std::{io::{Cursor, Write}, time::Duration};
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+use crate::service::{avatar_service, email_service, session_service};
+use crate::utils::{config::Config, error::AppError, password::hash_password, token::{generate_token, hash_token}};
+
+const EXPORT_TTL_DAYS: i64 = 7;
+// Un export au plus par période, pour limiter la charge
+const EXPORT_COOLDOWN_HOURS: i64 = 24;
+// Export encore 'pending' au-delà de ce délai : la tâche a été perdue (redémarrage du serveur)
+const EXPORT_STALE_MINUTES: i32 = 15;
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+// Fichiers de l'archive : données de chaque table rattachées à l'utilisateur
+const EXPORT_QUERIES: &[(&str, &str)] = &[
+    ("enrollments.json", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.created_at), '[]') FROM enrollments t WHERE t.user_id = $1"),
+    ("reviews.json", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.created_at), '[]') FROM reviews t WHERE t.user_id = $1"),
+    ("comments.json", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.created_at), '[]') FROM comments t WHERE t.user_id = $1"),
+    ("subscriptions.json", "SELECT coalesce(jsonb_agg(to_jsonb(t)), '[]') FROM subscriptions t WHERE t.user_id = $1"),
+    ("notification_preferences.json", "SELECT coalesce(jsonb_agg(to_jsonb(t) - 'user_id'), '[]') FROM notification_preferences t WHERE t.user_id = $1"),
+    ("progress.json", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.completed_at), '[]') FROM lesson_progress t WHERE t.user_id = $1"),
+    ("sessions.json", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.created_at), '[]') FROM sessions t WHERE t.user_id = $1"),
+    ("provider_identities.json", "SELECT coalesce(jsonb_agg(to_jsonb(t) ORDER BY t.created_at), '[]') FROM provider_identities t WHERE t.user_id = $1"),
+    ("api_keys.json", "SELECT coalesce(jsonb_agg(to_jsonb(t) - 'key_hash' ORDER BY t.created_at), '[]') FROM api_keys t WHERE t.user_id = $1"),
+    // Actions faites par un tiers (admin) sur le compte : l'adresse IP et l'agent de ce tiers ne sont pas exportés
+    ("audit_log.json", "SELECT coalesce(jsonb_agg(CASE WHEN t.actor_id = $1 THEN to_jsonb(t) ELSE to_jsonb(t) - 'actor_id' - 'ip' - 'user_agent' END ORDER BY t.created_at), '[]') FROM audit_log t WHERE t.actor_id = $1 OR t.target_id = $1"),
+];
+
+// Lance la construction de l'archive en tâche de fond ; le lien de téléchargement est envoyé par email
+pub async fn request_export(pool: &PgPool, cfg: &Config, user_id: Uuid) -> Result<Uuid, AppError> {
+    let recent = sqlx::query("SELECT GREATEST(EXTRACT(EPOCH FROM created_at + make_interval(hours => $2) - now()), 1)::float8 AS wait FROM data_exports WHERE user_id = $1 AND status <> 'failed' AND NOT (status = 'pending' AND created_at < now() - make_interval(mins => $3)) AND created_at > now() - make_interval(hours => $2) ORDER BY created_at DESC LIMIT 1")
+        .bind(user_id)
+        .bind(EXPORT_COOLDOWN_HOURS as i32)
+        .bind(EXPORT_STALE_MINUTES)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    if let Some(r) = recent { return Err(AppError::TooManyRequests { retry_after_secs: r.get::<f64, _>("wait").ceil() as u64 }) }
+
+    let row = sqlx::query("INSERT INTO data_exports (user_id) VALUES ($1) RETURNING id").bind(user_id).fetch_one(pool).await.map_err(|_| AppError::Internal)?;
+    let export_id: Uuid = row.get("id");
+    let (pool, cfg) = (pool.clone(), cfg.clone());
+    tokio::spawn(async move {
+        if let Err(e) = build_export(&pool, &cfg, export_id, user_id).await {
+            tracing::error!(error = %e, %export_id, "data export failed");
+            let _ = sqlx::query("UPDATE data_exports SET status = 'failed', completed_at = now() WHERE id = $1").bind(export_id).execute(&pool).await;
+        }
+    });
+    Ok(export_id)
+}
+
+async fn build_export(pool: &PgPool, cfg: &Config, export_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    // Les secrets d'authentification ne font pas partie des données exportées
+    let profile = sqlx::query("SELECT to_jsonb(u) - 'password_hash' - 'totp_secret' - 'totp_last_step' AS data, email FROM users u WHERE id = $1").bind(user_id).fetch_one(pool).await.map_err(|_| AppError::Internal)?;
+    let email: String = profile.get("email");
+    let mut files: Vec<(&str, Value)> = vec![("profile.json", profile.get("data"))];
+    for (name, query) in EXPORT_QUERIES {
+        let row = sqlx::query(query).bind(user_id).fetch_one(pool).await.map_err(|_| AppError::Internal)?;
+        files.push((name, row.get(0)));
+    }
+
+    let archive = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, AppError> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, data) in files {
+            zip.start_file(name, options).map_err(|_| AppError::Internal)?;
+            zip.write_all(&serde_json::to_vec_pretty(&data).map_err(|_| AppError::Internal)?).map_err(|_| AppError::Internal)?;
+        }
+        Ok(zip.finish().map_err(|_| AppError::Internal)?.into_inner())
+    }).await.map_err(|_| AppError::Internal)??;
+
+    let token = generate_token();
+    sqlx::query("UPDATE data_exports SET status = 'ready', archive = $2, token_hash = $3, expires_at = now() + make_interval(days => $4), completed_at = now() WHERE id = $1")
+        .bind(export_id)
+        .bind(archive)
+        .bind(hash_token(&token))
+        .bind(EXPORT_TTL_DAYS as i32)
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let link = format!("{}/api/profile/export/{}", cfg.api_url.trim_end_matches('/'), token);
+    let body = format!("Bonjour,\n\nL'archive de vos données WindevExpert est prête. Elle peut être téléchargée pendant {} jours :\n{}\n\nSi vous n'êtes pas à l'origine de cette demande, changez votre mot de passe.", EXPORT_TTL_DAYS, link);
+    email_service::spawn_send(cfg.smtp_config.clone(), email, "Votre export de données est prêt".to_string(), body);
+    Ok(())
+}
+
+pub async fn download_export(pool: &PgPool, token: &str) -> Result<Vec<u8>, AppError> {
+    let row = sqlx::query("SELECT archive FROM data_exports WHERE token_hash = $1 AND status = 'ready' AND expires_at > now()")
+        .bind(hash_token(token))
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    row.and_then(|r| r.get::<Option<Vec<u8>>, _>("archive")).ok_or(AppError::NotFound)
+}
+
+// Demande de suppression : abonnements Stripe résiliés et sessions fermées tout de suite,
+// données personnelles effacées à la fin du délai de grâce
+pub async fn request_deletion(pool: &PgPool, cfg: &Config, user_id: Uuid, email: &str) -> Result<(), AppError> {
+    cancel_subscriptions(pool, cfg, user_id).await?;
+    let mut tx = pool.begin().await.map_err(|_| AppError::Internal)?;
+    sqlx::query("UPDATE users SET deletion_requested_at = now(), updated_at = now() WHERE id = $1").bind(user_id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    session_service::revoke_all_sessions(&mut *tx, user_id, None).await?;
+    sqlx::query("UPDATE api_keys SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL").bind(user_id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+
+    let deadline = Utc::now() + chrono::Duration::days(cfg.account_deletion_grace_days);
+    let body = format!("Bonjour,\n\nLa suppression de votre compte WindevExpert a été demandée. Vos abonnements ont été résiliés et vos données personnelles seront effacées le {}.\n\nPour annuler, il vous suffit de vous reconnecter avant cette date.", deadline.format("%d/%m/%Y"));
+    email_service::spawn_send(cfg.smtp_config.clone(), email.to_string(), "Suppression de votre compte".to_string(), body);
+    Ok(())
+}
+
+async fn cancel_subscriptions(pool: &PgPool, cfg: &Config, user_id: Uuid) -> Result<(), AppError> {
+    let rows = sqlx::query("SELECT id, stripe_subscription_id FROM subscriptions WHERE user_id = $1 AND COALESCE(status, '') NOT IN ('canceled', 'incomplete_expired')")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    if rows.is_empty() { return Ok(()) }
+    let secret = cfg.stripe_keys.clone().ok_or(AppError::Internal)?;
+    let client = reqwest::Client::new();
+    for row in rows {
+        let subscription: String = row.get("stripe_subscription_id");
+        // https://stripe.com/docs/api/subscriptions/cancel
+        let response = client.delete(format!("https://api.stripe.com/v1/subscriptions/{}", urlencoding::encode(&subscription))).basic_auth(&secret, Some("")).send().await.map_err(|_| AppError::Internal)?;
+        // 404 : abonnement déjà supprimé côté Stripe
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND {
+            tracing::error!(status = %response.status(), subscription, "cannot cancel Stripe subscription");
+            return Err(AppError::Internal);
+        }
+        sqlx::query("UPDATE subscriptions SET status = 'canceled' WHERE id = $1").bind(row.get::<Uuid, _>("id")).execute(pool).await.map_err(|_| AppError::Internal)?;
+    }
+    Ok(())
+}
+
+// Efface les données personnelles des comptes dont le délai de grâce est écoulé. La ligne users est
+// conservée sans identité : avis et commentaires restent publiés mais anonymes, les paiements restent comptabilisés.
+pub async fn purge_due_accounts(pool: &PgPool, cfg: &Config) -> Result<(), AppError> {
+    let due = sqlx::query("SELECT id, avatar_url FROM users WHERE deletion_requested_at < now() - make_interval(days => $1) AND deleted_at IS NULL")
+        .bind(cfg.account_deletion_grace_days as i32)
+        .fetch_all(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    for row in due {
+        let user_id: Uuid = row.get("id");
+        let mut tx = pool.begin().await.map_err(|_| AppError::Internal)?;
+        sqlx::query("UPDATE users SET email = 'deleted-' || id || '@deleted.invalid', password_hash = $2, role = 'user', stripe_customer_id = NULL, full_name = NULL, bio = NULL, avatar_url = NULL, job_title = NULL, company = NULL, city = NULL, country = NULL, linkedin_url = NULL, website_url = NULL, pcsoft_experience = NULL, phone_number = NULL, last_login_at = NULL, email_verified_at = NULL, totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL, public_profile_fields = '{}', deletion_requested_at = NULL, deleted_at = now(), updated_at = now() WHERE id = $1")
+            .bind(user_id)
+            .bind(hash_password(&generate_token())?)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        for table in ["provider_identities", "api_keys", "sessions", "refresh_tokens", "recovery_codes", "password_reset_tokens", "email_verification_tokens", "magic_link_tokens", "data_exports", "lesson_progress", "notification_preferences"] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE user_id = $1")).bind(user_id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+        }
+        // Le journal d'audit est conservé, sans les adresses IP ni les agents
+        sqlx::query("UPDATE audit_log SET ip = NULL, user_agent = NULL WHERE actor_id = $1 OR target_id = $1").bind(user_id).execute(&mut *tx).await.map_err(|_| AppError::Internal)?;
+        tx.commit().await.map_err(|_| AppError::Internal)?;
+        if let Some(avatar_url) = row.get::<Option<String>, _>("avatar_url") {
+            avatar_service::remove_avatar_files(cfg, user_id, &avatar_url).await;
+        }
+        tracing::info!(%user_id, "account personal data purged");
+    }
+    sqlx::query("UPDATE data_exports SET status = 'failed', completed_at = now() WHERE status = 'pending' AND created_at < now() - make_interval(mins => $1)").bind(EXPORT_STALE_MINUTES).execute(pool).await.map_err(|_| AppError::Internal)?;
+    sqlx::query("DELETE FROM data_exports WHERE expires_at < now() OR (status = 'failed' AND created_at < now() - interval '1 day')").execute(pool).await.map_err(|_| AppError::Internal)?;
+    Ok(())
+}
+
+pub fn spawn_purge_task(pool: PgPool, cfg: Config) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge_due_accounts(&pool, &cfg).await {
+                tracing::error!(error = %e, "account purge failed");
+            }
+        }
+    });
+}
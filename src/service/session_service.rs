@@ -0,0 +1,126 @@
+use std::{collections::HashMap, net::IpAddr, sync::{LazyLock, Mutex}, time::{Duration, Instant}};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::utils::error::AppError;
+
+// Durée pendant laquelle l'état d'une session est servi depuis la mémoire sans relire la base
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+// sid -> (révoquée ?, date de vérification). Chaque instance a son propre cache :
+// une révocation faite ailleurs est prise en compte au plus tard après CACHE_TTL.
+static REVOCATION_CACHE: LazyLock<Mutex<HashMap<Uuid, (bool, Instant)>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo { pub ip: Option<IpAddr>, pub user_agent: Option<String> }
+
+#[derive(Serialize)]
+pub struct SessionDto {
+    pub id: String,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub current: bool,
+}
+
+// Libellé lisible de l'appareil, dérivé du User-Agent
+pub fn device_label(user_agent: &str) -> String {
+    let os = ["iPhone", "iPad", "Android", "Windows", "Mac OS", "Linux"].into_iter().find(|os| user_agent.contains(os)).unwrap_or("Unknown OS");
+    let browser = ["Edg/", "OPR/", "Firefox/", "Chrome/", "Safari/"].into_iter().find(|b| user_agent.contains(b)).map(|b| match b {
+        "Edg/" => "Edge",
+        "OPR/" => "Opera",
+        other => other.trim_end_matches('/'),
+    }).unwrap_or("Unknown browser");
+    format!("{browser} on {os}")
+}
+
+pub async fn create_session<'e, E: sqlx::PgExecutor<'e>>(executor: E, user_id: Uuid, client: &ClientInfo) -> Result<Uuid, AppError> {
+    let row = sqlx::query("INSERT INTO sessions (user_id, device, ip, user_agent) VALUES ($1, $2, $3, $4) RETURNING id")
+        .bind(user_id)
+        .bind(client.user_agent.as_deref().map(device_label))
+        .bind(client.ip.map(|ip| ip.to_string()))
+        .bind(client.user_agent.as_deref())
+        .fetch_one(executor)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(row.get("id"))
+}
+
+pub async fn touch_session<'e, E: sqlx::PgExecutor<'e>>(executor: E, session_id: Uuid, client: &ClientInfo) -> Result<(), AppError> {
+    sqlx::query("UPDATE sessions SET last_seen_at = now(), ip = COALESCE($2, ip), user_agent = COALESCE($3, user_agent) WHERE id = $1")
+        .bind(session_id)
+        .bind(client.ip.map(|ip| ip.to_string()))
+        .bind(client.user_agent.as_deref())
+        .execute(executor)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(())
+}
+
+// Vérifie qu'une session n'a pas été révoquée (déconnexion, reset du mot de passe...)
+pub async fn is_session_active(pool: &PgPool, session_id: Uuid) -> Result<bool, AppError> {
+    if let Some((revoked, checked_at)) = REVOCATION_CACHE.lock().unwrap().get(&session_id) {
+        if checked_at.elapsed() < CACHE_TTL { return Ok(!revoked) }
+    }
+    let row = sqlx::query("UPDATE sessions SET last_seen_at = now() WHERE id = $1 RETURNING revoked_at IS NOT NULL AS revoked")
+        .bind(session_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let revoked = row.map(|r| r.get::<bool, _>("revoked")).unwrap_or(true);
+    let mut cache = REVOCATION_CACHE.lock().unwrap();
+    cache.retain(|_, (_, checked_at)| checked_at.elapsed() < CACHE_TTL);
+    cache.insert(session_id, (revoked, Instant::now()));
+    Ok(!revoked)
+}
+
+pub async fn revoke_session(pool: &PgPool, user_id: Uuid, session_id: Uuid) -> Result<bool, AppError> {
+    let mut tx = pool.begin().await.map_err(|_| AppError::Internal)?;
+    let updated = sqlx::query("UPDATE sessions SET revoked_at = now() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL")
+        .bind(session_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = now() WHERE family_id = $1 AND user_id = $2 AND revoked_at IS NULL")
+        .bind(session_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    tx.commit().await.map_err(|_| AppError::Internal)?;
+    if updated.rows_affected() > 0 { mark_revoked([session_id]) }
+    Ok(updated.rows_affected() > 0)
+}
+
+// Révoque toutes les sessions d'un utilisateur, sauf éventuellement la session courante
+pub async fn revoke_all_sessions<'e, E: sqlx::PgExecutor<'e>>(executor: E, user_id: Uuid, except: Option<Uuid>) -> Result<(), AppError> {
+    let rows = sqlx::query("WITH revoked AS (UPDATE sessions SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL AND id IS DISTINCT FROM $2 RETURNING id), tokens AS (UPDATE refresh_tokens SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL AND family_id IS DISTINCT FROM $2) SELECT id FROM revoked")
+        .bind(user_id)
+        .bind(except)
+        .fetch_all(executor)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    mark_revoked(rows.iter().map(|r| r.get::<Uuid, _>("id")));
+    Ok(())
+}
+
+fn mark_revoked(ids: impl IntoIterator<Item = Uuid>) {
+    let mut cache = REVOCATION_CACHE.lock().unwrap();
+    for id in ids { cache.insert(id, (true, Instant::now())); }
+}
+
+pub async fn list_sessions(pool: &PgPool, user_id: Uuid, current: Option<Uuid>) -> Result<Vec<SessionDto>, AppError> {
+    let rows = sqlx::query("SELECT id, device, ip, user_agent, created_at, last_seen_at FROM sessions WHERE user_id = $1 AND revoked_at IS NULL ORDER BY last_seen_at DESC")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(rows.into_iter().map(|r| {
+        let id: Uuid = r.get("id");
+        SessionDto { id: id.to_string(), device: r.get("device"), ip: r.get("ip"), user_agent: r.get("user_agent"), created_at: r.get("created_at"), last_seen_at: r.get("last_seen_at"), current: Some(id) == current }
+    }).collect())
+}
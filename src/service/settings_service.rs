@@ -0,0 +1,27 @@
+use serde_json::Value;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::utils::error::AppError;
+
+// Image affichée pour les utilisateurs sans avatar public
+pub const DEFAULT_AVATAR_URL: &str = "default_avatar_url";
+
+async fn get(pool: &PgPool, key: &str) -> Result<Option<Value>, AppError> {
+    let row = sqlx::query("SELECT value FROM app_settings WHERE key = $1").bind(key).fetch_optional(pool).await.map_err(|_| AppError::Internal)?;
+    Ok(row.map(|r| r.get("value")))
+}
+
+pub async fn set(pool: &PgPool, key: &str, value: Value, admin_id: Uuid) -> Result<(), AppError> {
+    sqlx::query("INSERT INTO app_settings (key, value, updated_by) VALUES ($1, $2, $3) ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, updated_by = EXCLUDED.updated_by, updated_at = now()")
+        .bind(key)
+        .bind(value)
+        .bind(admin_id)
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(())
+}
+
+pub async fn get_string(pool: &PgPool, key: &str) -> Result<Option<String>, AppError> {
+    Ok(get(pool, key).await?.and_then(|v| v.as_str().map(str::to_string)))
+}
@@ -0,0 +1,110 @@
+use rand::RngCore;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+use crate::service::auth_service;
+use crate::utils::{config::Config, error::AppError, token::hash_token, totp};
+
+const RECOVERY_CODE_COUNT: usize = 10;
+// Au-delà, le jeton mfa_token est inutilisable : il faut repasser par le mot de passe
+pub const MAX_MFA_TOKEN_FAILURES: i32 = 5;
+
+// Codes de secours au format "xxxxx-xxxxx", affichés une seule fois ; seul leur hash est conservé
+pub async fn regenerate_recovery_codes<'e, E: sqlx::PgExecutor<'e>>(executor: E, user_id: Uuid) -> Result<Vec<String>, AppError> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| {
+        let mut bytes = [0u8; 5];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let hex = hex::encode(bytes);
+        format!("{}-{}", &hex[..5], &hex[5..])
+    }).collect();
+    let hashes: Vec<String> = codes.iter().map(|c| hash_token(c)).collect();
+    sqlx::query("WITH cleared AS (DELETE FROM recovery_codes WHERE user_id = $1) INSERT INTO recovery_codes (user_id, code_hash) SELECT $1, unnest($2::text[])")
+        .bind(user_id)
+        .bind(&hashes)
+        .execute(executor)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(codes)
+}
+
+// Second facteur : code TOTP (non rejoué) ou code de secours encore inutilisé
+// Code demandé à un utilisateur déjà connecté : un jeton d'accès volé ne suffit pas à deviner le code,
+// les échecs comptent pour le verrouillage comme à la connexion
+pub async fn confirm_second_factor(pool: &PgPool, cfg: &Config, user_id: Uuid, code: &str) -> Result<(), AppError> {
+    auth_service::check_lockout(pool, user_id).await?;
+    if !verify_second_factor(pool, user_id, code).await? {
+        auth_service::record_failed_login(pool, cfg, user_id).await?;
+        return Err(AppError::Unauthorized);
+    }
+    Ok(())
+}
+
+pub async fn verify_second_factor(pool: &PgPool, user_id: Uuid, code: &str) -> Result<bool, AppError> {
+    let row = sqlx::query("SELECT totp_secret, totp_last_step FROM users WHERE id = $1 AND totp_enabled_at IS NOT NULL")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    let Some(row) = row else { return Ok(false) };
+    let secret: Option<String> = row.get("totp_secret");
+    let last_step: Option<i64> = row.get("totp_last_step");
+
+    if let Some(step) = secret.and_then(|s| totp::verify(&s, code, chrono::Utc::now().timestamp() as u64, last_step)) {
+        // Condition sur le pas précédent : deux requêtes concurrentes ne peuvent consommer le même code
+        let updated = sqlx::query("UPDATE users SET totp_last_step = $2 WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)")
+            .bind(user_id)
+            .bind(step)
+            .execute(pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        return Ok(updated.rows_affected() == 1);
+    }
+
+    let used = sqlx::query("UPDATE recovery_codes SET used_at = now() WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL")
+        .bind(user_id)
+        .bind(hash_token(&code.trim().to_lowercase()))
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    if used.rows_affected() == 1 {
+        tracing::info!(%user_id, "recovery code used for two-factor authentication");
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+// Un jeton mfa_token déjà consommé ou ayant épuisé ses essais est refusé
+pub async fn mfa_token_usable(pool: &PgPool, jti: &str) -> Result<bool, AppError> {
+    let row = sqlx::query("SELECT consumed_at IS NULL AND failures < $2 AS usable FROM mfa_token_attempts WHERE jti = $1")
+        .bind(jti)
+        .bind(MAX_MFA_TOKEN_FAILURES)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(row.is_none_or(|r| r.get("usable")))
+}
+
+pub async fn record_mfa_failure(pool: &PgPool, jti: &str, user_id: Uuid, expires_at: i64) -> Result<(), AppError> {
+    // Les lignes des jetons expirés ne servent plus à rien
+    sqlx::query("DELETE FROM mfa_token_attempts WHERE expires_at < now()").execute(pool).await.map_err(|_| AppError::Internal)?;
+    sqlx::query("INSERT INTO mfa_token_attempts (jti, user_id, failures, expires_at) VALUES ($1, $2, 1, to_timestamp($3)) ON CONFLICT (jti) DO UPDATE SET failures = mfa_token_attempts.failures + 1")
+        .bind(jti)
+        .bind(user_id)
+        .bind(expires_at as f64)
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(())
+}
+
+// Usage unique : renvoie false si une requête concurrente a déjà consommé le jeton
+pub async fn consume_mfa_token(pool: &PgPool, jti: &str, user_id: Uuid, expires_at: i64) -> Result<bool, AppError> {
+    let consumed = sqlx::query("INSERT INTO mfa_token_attempts (jti, user_id, consumed_at, expires_at) VALUES ($1, $2, now(), to_timestamp($3)) ON CONFLICT (jti) DO UPDATE SET consumed_at = now() WHERE mfa_token_attempts.consumed_at IS NULL AND mfa_token_attempts.failures < $4")
+        .bind(jti)
+        .bind(user_id)
+        .bind(expires_at as f64)
+        .bind(MAX_MFA_TOKEN_FAILURES)
+        .execute(pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(consumed.rows_affected() == 1)
+}
@@ -1,5 +1,8 @@
 use dotenvy::dotenv;
 use std::env;
+use std::net::IpAddr;
+use std::sync::Arc;
+use crate::utils::{jwt::JwtKeys, password_policy, storage::{self, Storage}};
 use anyhow::{Result, Context};
 use serde::Serialize;
 use hyper::header::HeaderValue;
@@ -8,20 +11,75 @@ use hyper::header::HeaderValue;
 pub struct Config {
     pub database_url: String,
     pub jwt_secret: String,
+    #[serde(skip)]
+    pub jwt_keys: Arc<JwtKeys>,
     pub port: u16,
     pub stripe_keys: Option<String>,
     pub stripe_webhook_secret: Option<String>,
     pub admin_auth: Option<String>,
     pub smtp_config: Option<String>,
     pub s3_config: Option<String>,
+    // Répertoire des fichiers publics quand S3_CONFIG est absent, servi sous /uploads
+    pub upload_dir: String,
+    #[serde(skip)]
+    pub storage: Arc<dyn Storage>,
     pub frontend_url: String,
+    pub access_token_ttl_minutes: i64,
+    pub refresh_token_ttl_days: i64,
+    pub require_verified_email: bool,
+    pub auth_rate_limit_per_minute: u32,
+    pub trusted_proxies: Vec<IpAddr>,
+    pub require_admin_2fa: bool,
+    pub login_lockout_threshold: i32,
+    pub password_min_length: usize,
+    pub password_min_score: u8,
+    pub breached_passwords_dir: Option<String>,
+    pub account_deletion_grace_days: i64,
+    pub api_url: String,
+    // Clé HMAC des liens de désinscription, distincte de JWT_SECRET (inutilisé en RS256/EdDSA) ;
+    // absente, les emails partent sans lien de désinscription et les jetons sont refusés
+    #[serde(skip)]
+    pub unsubscribe_secret: Option<String>,
+    pub oauth_providers: Vec<OAuthProvider>,
 }
 
+// Fournisseur de connexion externe : OpenID Connect (découverte via l'issuer) ou OAuth2 GitHub
+#[derive(Clone, Serialize, PartialEq)]
+pub enum OAuthKind { Oidc, GitHub }
+
+#[derive(Clone, Serialize)]
+pub struct OAuthProvider {
+    pub name: String,
+    pub kind: OAuthKind,
+    pub issuer: String,
+    pub client_id: String,
+    #[serde(skip)]
+    pub client_secret: String,
+}
+
+// OAUTH_<NOM>_CLIENT_ID / _CLIENT_SECRET / _ISSUER ; l'issuer est obligatoire pour le fournisseur générique "oidc"
+fn oauth_providers_from_env() -> Vec<OAuthProvider> {
+    [("google", Some("https://accounts.google.com")), ("linkedin", Some("https://www.linkedin.com/oauth")), ("github", Some("https://github.com")), ("oidc", None)]
+        .into_iter()
+        .filter_map(|(name, default_issuer)| {
+            let prefix = format!("OAUTH_{}", name.to_uppercase());
+            let client_id = env::var(format!("{prefix}_CLIENT_ID")).ok()?;
+            let client_secret = env::var(format!("{prefix}_CLIENT_SECRET")).unwrap_or_default();
+            let issuer = env::var(format!("{prefix}_ISSUER")).ok().or(default_issuer.map(str::to_string))?;
+            let kind = if name == "github" { OAuthKind::GitHub } else { OAuthKind::Oidc };
+            Some(OAuthProvider { name: name.to_string(), kind, issuer: issuer.trim_end_matches('/').to_string(), client_id, client_secret })
+        })
+        .collect()
+}
+
+const MIN_UNSUBSCRIBE_SECRET_LEN: usize = 32;
+
 impl Config {
     pub fn from_env() -> Result<Self> {
         let _ = dotenv();
         let database_url = env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
         let jwt_secret = env::var("JWT_SECRET").unwrap_or_else(|_| "change_me_default_secret".to_string());
+        let jwt_keys = Arc::new(JwtKeys::from_env(&jwt_secret)?);
         let port = env::var("PORT").ok().and_then(|v| v.parse().ok()).unwrap_or(8080);
         let stripe_keys = env::var("STRIPE_KEYS").ok();
         let stripe_webhook_secret = env::var("STRIPE_WEBHOOK_SECRET").ok();
@@ -29,7 +87,25 @@ impl Config {
         let smtp_config = env::var("SMTP_CONFIG").ok();
         let s3_config = env::var("S3_CONFIG").ok();
         let frontend_url = env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:5173".to_string());
-        Ok(Self { database_url, jwt_secret, port, stripe_keys, stripe_webhook_secret, admin_auth, smtp_config, s3_config, frontend_url })
+        let access_token_ttl_minutes = env::var("ACCESS_TOKEN_TTL_MINUTES").ok().and_then(|v| v.parse().ok()).unwrap_or(60);
+        let refresh_token_ttl_days = env::var("REFRESH_TOKEN_TTL_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(30);
+        let require_verified_email = env::var("REQUIRE_VERIFIED_EMAIL").ok().map(|v| v == "1" || v == "true").unwrap_or(false);
+        let auth_rate_limit_per_minute = env::var("AUTH_RATE_LIMIT_PER_MINUTE").ok().and_then(|v| v.parse().ok()).filter(|v| *v > 0).unwrap_or(5);
+        let trusted_proxies = env::var("TRUSTED_PROXIES").ok().map(|v| v.split(',').filter_map(|p| p.trim().parse().ok()).collect()).unwrap_or_default();
+        let require_admin_2fa = env::var("REQUIRE_ADMIN_2FA").ok().map(|v| v == "1" || v == "true").unwrap_or(false);
+        let login_lockout_threshold = env::var("LOGIN_LOCKOUT_THRESHOLD").ok().and_then(|v| v.parse().ok()).filter(|v| *v > 0).unwrap_or(5);
+        let password_min_length = env::var("PASSWORD_MIN_LENGTH").ok().and_then(|v| v.parse().ok()).unwrap_or(8);
+        let password_min_score = env::var("PASSWORD_MIN_SCORE").ok().and_then(|v| v.parse().ok()).unwrap_or(2).min(password_policy::MAX_SCORE);
+        // Répertoire de fichiers de plages SHA-1 (un fichier par préfixe de 5 caractères) ; liste embarquée sinon
+        let breached_passwords_dir = env::var("BREACHED_PASSWORDS_DIR").ok().filter(|v| !v.is_empty());
+        let account_deletion_grace_days = env::var("ACCOUNT_DELETION_GRACE_DAYS").ok().and_then(|v| v.parse().ok()).filter(|v| *v >= 0).unwrap_or(30);
+        let api_url = env::var("API_URL").unwrap_or_else(|_| format!("http://localhost:{port}"));
+        let upload_dir = env::var("UPLOAD_DIR").unwrap_or_else(|_| "uploads".to_string());
+        let storage = storage::from_env(s3_config.as_deref(), &upload_dir, &api_url)?;
+        let unsubscribe_secret = env::var("UNSUBSCRIBE_SECRET").ok().filter(|v| !v.is_empty());
+        if unsubscribe_secret.as_ref().is_some_and(|s| s.len() < MIN_UNSUBSCRIBE_SECRET_LEN) { anyhow::bail!("UNSUBSCRIBE_SECRET must be at least {MIN_UNSUBSCRIBE_SECRET_LEN} characters long") }
+        let oauth_providers = oauth_providers_from_env();
+        Ok(Self { database_url, jwt_secret, jwt_keys, port, stripe_keys, stripe_webhook_secret, admin_auth, smtp_config, s3_config, upload_dir, storage, frontend_url, access_token_ttl_minutes, refresh_token_ttl_days, require_verified_email, auth_rate_limit_per_minute, trusted_proxies, require_admin_2fa, login_lockout_threshold, password_min_length, password_min_score, breached_passwords_dir, account_deletion_grace_days, api_url, unsubscribe_secret, oauth_providers })
     }
 }
 
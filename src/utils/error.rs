@@ -1,5 +1,11 @@
-use axum::{http::StatusCode, response::{IntoResponse, Response}};
+use std::collections::BTreeMap;
+use axum::{http::{header, StatusCode}, response::{IntoResponse, Response}, Json};
+use serde_json::json;
 use thiserror::Error;
+
+// Messages d'erreur par champ de la requête, renvoyés en 422
+pub type FieldErrors = BTreeMap<String, Vec<String>>;
+
 #[allow(dead_code)]
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -13,13 +19,29 @@ pub enum AppError {
     BadRequest,
     #[error("Conflict")]
     Conflict,
+    #[error("Too many requests")]
+    TooManyRequests { retry_after_secs: u64 },
+    #[error("Validation failed")]
+    Validation(FieldErrors),
     #[error("Internal server error")]
     Internal,
 }
 
+impl AppError {
+    pub fn field(field: &str, message: impl Into<String>) -> Self {
+        AppError::Validation(BTreeMap::from([(field.to_string(), vec![message.into()])]))
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let code = match self { AppError::NotFound => StatusCode::NOT_FOUND, AppError::Unauthorized => StatusCode::UNAUTHORIZED, AppError::Forbidden => StatusCode::FORBIDDEN, AppError::BadRequest => StatusCode::BAD_REQUEST, AppError::Conflict => StatusCode::CONFLICT, AppError::Internal => StatusCode::INTERNAL_SERVER_ERROR };
+        if let AppError::TooManyRequests { retry_after_secs } = self {
+            return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after_secs.to_string())], self.to_string()).into_response();
+        }
+        if let AppError::Validation(fields) = &self {
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "error": self.to_string(), "fields": fields }))).into_response();
+        }
+        let code = match self { AppError::NotFound => StatusCode::NOT_FOUND, AppError::Unauthorized => StatusCode::UNAUTHORIZED, AppError::Forbidden => StatusCode::FORBIDDEN, AppError::BadRequest => StatusCode::BAD_REQUEST, AppError::Conflict => StatusCode::CONFLICT, AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS, AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY, AppError::Internal => StatusCode::INTERNAL_SERVER_ERROR };
         (code, self.to_string()).into_response()
     }
 }
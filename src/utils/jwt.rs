@@ -1,6 +1,12 @@
+use std::collections::HashMap;
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, Algorithm, TokenData};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use chrono::{Utc, Duration};
+use anyhow::{anyhow, bail, Context};
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine};
+use rsa::{pkcs8::DecodePublicKey, traits::PublicKeyParts, RsaPublicKey};
+use uuid::Uuid;
 use crate::utils::error::AppError;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -8,18 +14,113 @@ pub struct Claims {
     pub sub: String,
     pub role: String,
     pub exp: usize,
+    #[serde(default)]
+    pub jti: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    // Acteur réel lorsqu'un admin agit au nom de `sub` (RFC 8693)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
 }
 
-pub fn create_token(subject: &str, role: &str, secret: &str, ttl_minutes: i64) -> Result<String, AppError> {
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Actor { pub sub: String }
+
+struct VerifyingKey { alg: Algorithm, key: DecodingKey, jwk: Option<Value> }
+
+// Clés de signature et de vérification. En HS256 le secret partagé sert aux deux ;
+// en RS256/EdDSA seule la clé privée courante signe, et toutes les clés publiques
+// déclarées (courante + précédentes, pendant une rotation) vérifient et sont publiées dans le JWKS.
+pub struct JwtKeys {
+    kid: String,
+    alg: Algorithm,
+    signing: EncodingKey,
+    verifying: HashMap<String, VerifyingKey>,
+}
+
+impl JwtKeys {
+    pub fn from_env(secret: &str) -> anyhow::Result<Self> {
+        let kid = std::env::var("JWT_KEY_ID").unwrap_or_else(|_| "default".to_string());
+        let alg = match std::env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string()).as_str() {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "EdDSA" => Algorithm::EdDSA,
+            other => bail!("unsupported JWT_ALGORITHM {other}"),
+        };
+        if alg == Algorithm::HS256 {
+            let mut verifying = HashMap::new();
+            verifying.insert(kid.clone(), VerifyingKey { alg, key: DecodingKey::from_secret(secret.as_bytes()), jwk: None });
+            return Ok(JwtKeys { kid, alg, signing: EncodingKey::from_secret(secret.as_bytes()), verifying });
+        }
+
+        let private_pem = read_pem(&std::env::var("JWT_PRIVATE_KEY").context("JWT_PRIVATE_KEY must be set for asymmetric JWT")?)?;
+        let public_pem = read_pem(&std::env::var("JWT_PUBLIC_KEY").context("JWT_PUBLIC_KEY must be set for asymmetric JWT")?)?;
+        let signing = match alg {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(private_pem.as_bytes())?,
+            _ => EncodingKey::from_ed_pem(private_pem.as_bytes())?,
+        };
+        let mut verifying = HashMap::new();
+        verifying.insert(kid.clone(), load_public_key(&kid, &public_pem)?);
+        // JWT_PREVIOUS_PUBLIC_KEYS="kid1=/path/old.pem,kid2=/path/older.pem"
+        for entry in std::env::var("JWT_PREVIOUS_PUBLIC_KEYS").unwrap_or_default().split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (old_kid, source) = entry.split_once('=').ok_or_else(|| anyhow!("invalid JWT_PREVIOUS_PUBLIC_KEYS entry {entry}"))?;
+            verifying.insert(old_kid.to_string(), load_public_key(old_kid, &read_pem(source)?)?);
+        }
+        Ok(JwtKeys { kid, alg, signing, verifying })
+    }
+
+    // Document JWKS (RFC 7517) : vide en HS256, le secret n'étant jamais publié
+    pub fn jwks(&self) -> Value {
+        let keys: Vec<&Value> = self.verifying.values().filter_map(|k| k.jwk.as_ref()).collect();
+        json!({ "keys": keys })
+    }
+}
+
+// La variable contient soit le PEM lui-même, soit le chemin d'un fichier PEM
+fn read_pem(source: &str) -> anyhow::Result<String> {
+    if source.trim_start().starts_with("-----BEGIN") { return Ok(source.to_string()) }
+    std::fs::read_to_string(source).with_context(|| format!("cannot read key file {source}"))
+}
+
+fn load_public_key(kid: &str, pem: &str) -> anyhow::Result<VerifyingKey> {
+    if let Ok(rsa) = RsaPublicKey::from_public_key_pem(pem) {
+        let jwk = json!({ "kty": "RSA", "use": "sig", "alg": "RS256", "kid": kid, "n": URL_SAFE_NO_PAD.encode(rsa.n().to_bytes_be()), "e": URL_SAFE_NO_PAD.encode(rsa.e().to_bytes_be()) });
+        return Ok(VerifyingKey { alg: Algorithm::RS256, key: DecodingKey::from_rsa_pem(pem.as_bytes())?, jwk: Some(jwk) });
+    }
+    // SubjectPublicKeyInfo Ed25519 : 12 octets d'en-tête DER suivis des 32 octets de la clé
+    let body: String = pem.lines().filter(|l| !l.starts_with("-----")).collect();
+    let der = STANDARD.decode(body.trim()).context("invalid public key PEM")?;
+    if der.len() != 44 { bail!("public key {kid} is neither RSA nor Ed25519") }
+    let jwk = json!({ "kty": "OKP", "crv": "Ed25519", "use": "sig", "alg": "EdDSA", "kid": kid, "x": URL_SAFE_NO_PAD.encode(&der[12..]) });
+    Ok(VerifyingKey { alg: Algorithm::EdDSA, key: DecodingKey::from_ed_pem(pem.as_bytes())?, jwk: Some(jwk) })
+}
+
+pub fn create_token(subject: &str, role: &str, session_id: Option<Uuid>, keys: &JwtKeys, ttl_minutes: i64) -> Result<String, AppError> {
     let exp = (Utc::now() + Duration::minutes(ttl_minutes)).timestamp() as usize;
-    let claims = Claims { sub: subject.to_string(), role: role.to_string(), exp };
-    let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(secret.as_bytes())).map_err(|_| AppError::Internal)?;
+    let claims = Claims { sub: subject.to_string(), role: role.to_string(), exp, jti: Uuid::new_v4().to_string(), sid: session_id.map(|s| s.to_string()), act: None };
+    encode(&claims, keys)
+}
+
+// Jeton d'usurpation : sans session ni refresh token, il expire simplement
+pub fn create_impersonation_token(subject: &str, role: &str, actor: &str, keys: &JwtKeys, ttl_minutes: i64) -> Result<String, AppError> {
+    let exp = (Utc::now() + Duration::minutes(ttl_minutes)).timestamp() as usize;
+    let claims = Claims { sub: subject.to_string(), role: role.to_string(), exp, jti: Uuid::new_v4().to_string(), sid: None, act: Some(Actor { sub: actor.to_string() }) };
+    encode(&claims, keys)
+}
+
+fn encode(claims: &Claims, keys: &JwtKeys) -> Result<String, AppError> {
+    let mut header = Header::new(keys.alg);
+    header.kid = Some(keys.kid.clone());
+    let token = jsonwebtoken::encode(&header, claims, &keys.signing).map_err(|_| AppError::Internal)?;
     Ok(token)
 }
 
-#[allow(dead_code)]
-pub fn validate_token(token: &str, secret: &str) -> Result<TokenData<Claims>, AppError> {
-    let data = jsonwebtoken::decode::<Claims>(token, &DecodingKey::from_secret(secret.as_bytes()), &Validation::new(Algorithm::HS256)).map_err(|_| AppError::Unauthorized)?;
+pub fn validate_token(token: &str, keys: &JwtKeys) -> Result<TokenData<Claims>, AppError> {
+    let header = jsonwebtoken::decode_header(token).map_err(|_| AppError::Unauthorized)?;
+    // Les jetons émis avant l'ajout du `kid` sont vérifiés avec la clé courante
+    let kid = header.kid.unwrap_or_else(|| keys.kid.clone());
+    let key = keys.verifying.get(&kid).ok_or(AppError::Unauthorized)?;
+    if header.alg != key.alg { return Err(AppError::Unauthorized) }
+    let data = jsonwebtoken::decode::<Claims>(token, &key.key, &Validation::new(key.alg)).map_err(|_| AppError::Unauthorized)?;
     Ok(data)
 }
-
@@ -1,4 +1,10 @@
 pub mod config;
 pub mod error;
 pub mod jwt;
-
+pub mod password;
+pub mod password_policy;
+pub mod token;
+pub mod rate_limit;
+pub mod storage;
+pub mod totp;
+pub mod validation;
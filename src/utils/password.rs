@@ -0,0 +1,21 @@
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::{SaltString, rand_core::OsRng};
+use crate::utils::error::AppError;
+
+pub fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(password.as_bytes(), &salt).map_err(|_| AppError::Internal)?;
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> Result<(), AppError> {
+    let parsed = PasswordHash::new(password_hash).map_err(|_| AppError::Internal)?;
+    Argon2::default().verify_password(password.as_bytes(), &parsed).map_err(|_| AppError::Unauthorized)
+}
+
+// Hash de référence vérifié quand l'email est inconnu, pour que la durée de réponse ne révèle pas l'existence du compte
+pub fn verify_dummy(password: &str) {
+    static DUMMY_HASH: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    let hash = DUMMY_HASH.get_or_init(|| hash_password("dummy-password-for-timing").unwrap_or_default());
+    let _ = verify_password(password, hash);
+}
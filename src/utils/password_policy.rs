@@ -0,0 +1,157 @@
+use std::{collections::HashSet, path::Path, sync::LazyLock};
+use sha1::{Digest, Sha1};
+use crate::utils::{config::Config, error::{AppError, FieldErrors}};
+
+// Liste embarquée, utilisée quand aucun répertoire de plages n'est configuré
+static BUNDLED_BREACHED: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    include_str!("breached_passwords.txt").lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')).collect()
+});
+
+// Motifs que les attaquants essaient en premier : comptés comme un seul élément de dictionnaire
+const COMMON_PATTERNS: &[&str] = &["password", "motdepasse", "passw0rd", "azerty", "qwerty", "qwertz", "asdf", "zxcv", "admin", "welcome", "bonjour", "soleil", "iloveyou", "jetaime", "windev", "webdev", "pcsoft", "letmein", "dragon", "monkey", "football", "master"];
+// Coût en bits d'un mot du dictionnaire d'un attaquant (~1000 entrées), d'une donnée personnelle, d'une année
+const DICTIONARY_BITS: f64 = 10.0;
+const PERSONAL_BITS: f64 = 4.0;
+const YEAR_BITS: f64 = 7.0;
+// Recherche exhaustive : 10 essais par caractère, comme zxcvbn
+const BRUTEFORCE_BITS: f64 = 3.32;
+
+pub const MAX_SCORE: u8 = 4;
+
+// Score de robustesse 0..=4 à la manière de zxcvbn : estimation du nombre d'essais nécessaires,
+// en ne comptant presque rien pour les répétitions, suites, motifs courants et données personnelles
+pub fn strength_score(password: &str, user_inputs: &[&str]) -> u8 {
+    let mut masked: Vec<Option<char>> = password.to_lowercase().chars().map(Some).collect();
+    let personal: Vec<String> = user_inputs.iter().flat_map(|i| i.to_lowercase().split(|c: char| !c.is_alphanumeric()).map(str::to_string).collect::<Vec<_>>()).filter(|w| w.chars().count() >= 3).collect();
+    let years: Vec<String> = (1900..2100).map(|y: u32| y.to_string()).collect();
+    let mut bits = mask_tokens(&mut masked, COMMON_PATTERNS.iter().copied(), DICTIONARY_BITS)
+        + mask_tokens(&mut masked, personal.iter().map(String::as_str), PERSONAL_BITS)
+        + mask_tokens(&mut masked, years.iter().map(String::as_str), YEAR_BITS);
+
+    let mut prev: Option<char> = None;
+    for c in masked {
+        bits += match (prev, c) {
+            (_, None) => 0.0,
+            // Répétition ou suite (abc, 321) : quasiment aucune information nouvelle
+            (Some(p), Some(c)) if p == c || (p as i64 - c as i64).abs() == 1 => 1.0,
+            _ => BRUTEFORCE_BITS,
+        };
+        prev = c;
+    }
+    // Mélange de classes de caractères (minuscules, majuscules, chiffres, symboles)
+    let classes: [fn(char) -> bool; 4] = [char::is_lowercase, char::is_uppercase, |c| c.is_ascii_digit(), |c| !c.is_alphanumeric()];
+    let classes = classes.iter().filter(|class| password.chars().any(**class)).count();
+    bits += classes.saturating_sub(1) as f64 * 2.0;
+
+    // Seuils de zxcvbn : 10^3, 10^6, 10^8, 10^10 essais
+    match bits {
+        b if b < 10.0 => 0,
+        b if b < 20.0 => 1,
+        b if b < 26.6 => 2,
+        b if b < 33.2 => 3,
+        _ => MAX_SCORE,
+    }
+}
+
+// Remplace chaque occurrence des motifs par None et renvoie leur coût cumulé
+fn mask_tokens<'a>(masked: &mut [Option<char>], tokens: impl Iterator<Item = &'a str>, cost: f64) -> f64 {
+    let mut bits = 0.0;
+    for token in tokens {
+        let token: Vec<char> = token.chars().collect();
+        let mut i = 0;
+        while i + token.len() <= masked.len() {
+            if masked[i..i + token.len()].iter().zip(&token).all(|(c, t)| *c == Some(*t)) {
+                masked[i..i + token.len()].iter_mut().for_each(|c| *c = None);
+                bits += cost;
+                i += token.len();
+            } else {
+                i += 1;
+            }
+        }
+    }
+    bits
+}
+
+// Recherche par k-anonymat : seul le préfixe de 5 caractères de l'empreinte SHA-1 désigne le fichier
+// de plage à lire (format Have I Been Pwned, lignes "SUFFIXE:COMPTE"), le mot de passe ne quitte jamais le serveur
+pub async fn is_breached(password: &str, ranges_dir: Option<&str>) -> Result<bool, AppError> {
+    let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let Some(dir) = ranges_dir else { return Ok(BUNDLED_BREACHED.contains(digest.as_str())) };
+    let (prefix, suffix) = digest.split_at(5);
+    match tokio::fs::read_to_string(Path::new(dir).join(prefix)).await {
+        Ok(range) => Ok(range.lines().filter_map(|l| l.split(':').next()).any(|s| s.trim().eq_ignore_ascii_case(suffix))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => {
+            tracing::error!(error = %e, dir, "cannot read breached password range");
+            Err(AppError::Internal)
+        }
+    }
+}
+
+// Applique la politique de mot de passe ; `user_inputs` (email, nom) ne doit pas rendre le mot de passe devinable
+pub async fn check(cfg: &Config, password: &str, user_inputs: &[&str]) -> Result<(), AppError> {
+    let mut messages = Vec::new();
+    if password.chars().count() < cfg.password_min_length {
+        messages.push(format!("Password must be at least {} characters long", cfg.password_min_length));
+    } else if strength_score(password, user_inputs) < cfg.password_min_score {
+        messages.push("Password is too easy to guess".to_string());
+    }
+    if is_breached(password, cfg.breached_passwords_dir.as_deref()).await? {
+        messages.push("Password appears in a list of breached passwords".to_string());
+    }
+    if messages.is_empty() { return Ok(()) }
+    Err(AppError::Validation(FieldErrors::from([("password".to_string(), messages)])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_guessable_passwords_low() {
+        for password in ["password", "azerty123", "aaaaaaaaaaaa", "abcdefghijkl", "987654321", "windev2025"] {
+            assert!(strength_score(password, &[]) <= 1, "{password} scored {}", strength_score(password, &[]));
+        }
+    }
+
+    #[test]
+    fn personal_data_does_not_count_as_entropy() {
+        let inputs = ["jean.dupont@example.com", "Jean Dupont"];
+        let password = "dupontjean1987";
+        assert!(strength_score(password, &inputs) < strength_score(password, &[]));
+        assert!(strength_score(password, &inputs) <= 1);
+    }
+
+    #[test]
+    fn scores_long_mixed_passwords_high() {
+        assert_eq!(strength_score("green-Zebra-88", &[]), MAX_SCORE);
+        assert_eq!(strength_score("t7#Qm!vR2xLp", &[]), MAX_SCORE);
+        assert!(strength_score("correct horse battery staple", &[]) >= 3);
+    }
+
+    #[tokio::test]
+    async fn bundled_list_detects_common_breached_passwords() {
+        for password in ["password", "123456", "qwerty"] {
+            assert!(is_breached(password, None).await.unwrap(), "{password}");
+        }
+        assert!(!is_breached("green-Zebra-88-unlisted", None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn range_files_are_looked_up_by_sha1_prefix() {
+        // "password" : SHA-1 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+        let dir = std::env::temp_dir().join(format!("breached-ranges-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("5BAA6"), "003D68EB55068C33ACE09247EE4C639306B:3\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n").unwrap();
+        let dir_str = dir.to_str().unwrap();
+
+        assert!(is_breached("password", Some(dir_str)).await.unwrap());
+        // Plage présente mais suffixe absent
+        let other = hex::encode_upper(Sha1::digest(b"green-Zebra-88"));
+        std::fs::write(dir.join(&other[..5]), "0000000000000000000000000000000000A:1\n").unwrap();
+        assert!(!is_breached("green-Zebra-88", Some(dir_str)).await.unwrap());
+        // Fichier de plage inexistant : non compromis
+        assert!(!is_breached("t7#Qm!vR2xLp", Some(dir_str)).await.unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use std::{net::{IpAddr, SocketAddr}, num::NonZeroU32, sync::Arc, time::Duration};
+use axum::{body::{to_bytes, Body}, extract::{ConnectInfo, Request, State}, http::HeaderMap, middleware::Next, response::Response};
+use governor::{clock::{Clock, DefaultClock}, DefaultKeyedRateLimiter, Quota, RateLimiter};
+use crate::utils::error::AppError;
+
+const MAX_INSPECTED_BODY: usize = 64 * 1024;
+
+// Limiteur partagé : un quota par IP cliente et un quota par adresse email ciblée
+#[derive(Clone)]
+pub struct RateLimit {
+    by_ip: Arc<DefaultKeyedRateLimiter<IpAddr>>,
+    by_email: Arc<DefaultKeyedRateLimiter<String>>,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+}
+
+impl RateLimit {
+    pub fn per_minute(limit: u32, trusted_proxies: Vec<IpAddr>) -> Self {
+        let quota = Quota::per_minute(NonZeroU32::new(limit).unwrap_or(NonZeroU32::MIN));
+        let limiter = RateLimit { by_ip: Arc::new(RateLimiter::keyed(quota)), by_email: Arc::new(RateLimiter::keyed(quota)), trusted_proxies: Arc::new(trusted_proxies) };
+        // Purge périodique des clés inactives pour borner la mémoire
+        let cleanup = limiter.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60));
+            loop {
+                interval.tick().await;
+                cleanup.by_ip.retain_recent();
+                cleanup.by_email.retain_recent();
+            }
+        });
+        limiter
+    }
+
+    pub fn check_ip(&self, ip: IpAddr) -> Result<(), AppError> { check(&self.by_ip, &ip) }
+
+    pub fn check_email(&self, email: &str) -> Result<(), AppError> { check(&self.by_email, &email.trim().to_lowercase()) }
+}
+
+// IP du client : X-Forwarded-For n'est pris en compte que si la connexion vient d'un proxy de confiance
+pub fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted_proxies.contains(&peer) { return Some(peer) }
+    let forwarded = headers.get("x-forwarded-for").and_then(|v| v.to_str().ok()).unwrap_or("");
+    // On remonte la chaîne depuis la droite jusqu'au premier saut non fiable
+    let client = forwarded.split(',').rev().filter_map(|p| p.trim().parse::<IpAddr>().ok()).find(|ip| !trusted_proxies.contains(ip));
+    Some(client.unwrap_or(peer))
+}
+
+fn check<K: std::hash::Hash + Eq + Clone>(limiter: &DefaultKeyedRateLimiter<K>, key: &K) -> Result<(), AppError> {
+    limiter.check_key(key).map_err(|not_until| {
+        let wait = not_until.wait_time_from(DefaultClock::default().now());
+        AppError::TooManyRequests { retry_after_secs: wait.as_secs().max(1) }
+    })
+}
+
+pub async fn limit(State(limiter): State<RateLimit>, req: Request, next: Next) -> Result<Response, AppError> {
+    let peer = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|c| c.0.ip());
+    if let Some(ip) = client_ip(peer, req.headers(), &limiter.trusted_proxies) {
+        limiter.check_ip(ip)?;
+    }
+
+    // Le corps JSON est lu pour connaître l'email visé, puis reconstitué pour le handler
+    let (parts, body) = req.into_parts();
+    let bytes = to_bytes(body, MAX_INSPECTED_BODY).await.map_err(|_| AppError::BadRequest)?;
+    if let Ok(value) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+        if let Some(email) = value.get("email").and_then(|v| v.as_str()) {
+            limiter.check_email(email)?;
+        }
+    }
+    Ok(next.run(Request::from_parts(parts, Body::from(bytes))).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr { s.parse().unwrap() }
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn ignores_forwarded_header_from_untrusted_peer() {
+        let trusted = [ip("10.0.0.1")];
+        assert_eq!(client_ip(Some(ip("203.0.113.9")), &forwarded("198.51.100.7"), &trusted), Some(ip("203.0.113.9")));
+        assert_eq!(client_ip(Some(ip("203.0.113.9")), &forwarded("198.51.100.7"), &[]), Some(ip("203.0.113.9")));
+        assert_eq!(client_ip(None, &forwarded("198.51.100.7"), &trusted), None);
+    }
+
+    #[test]
+    fn takes_first_untrusted_hop_from_the_right() {
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+        // Le client peut préfixer une fausse adresse : seule la dernière adresse ajoutée par un proxy de confiance compte
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), &forwarded("1.2.3.4, 198.51.100.7, 10.0.0.2"), &trusted), Some(ip("198.51.100.7")));
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), &forwarded(" 2001:db8::1 "), &trusted), Some(ip("2001:db8::1")));
+        // Entrées illisibles ignorées
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), &forwarded("198.51.100.7, not-an-ip"), &trusted), Some(ip("198.51.100.7")));
+    }
+
+    #[test]
+    fn falls_back_to_peer_without_usable_forwarded_header() {
+        let trusted = [ip("10.0.0.1"), ip("10.0.0.2")];
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), &HeaderMap::new(), &trusted), Some(ip("10.0.0.1")));
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), &forwarded("10.0.0.2"), &trusted), Some(ip("10.0.0.1")));
+        assert_eq!(client_ip(Some(ip("10.0.0.1")), &forwarded("garbage"), &trusted), Some(ip("10.0.0.1")));
+    }
+}
@@ -0,0 +1,152 @@
+use std::{path::PathBuf, sync::Arc};
+use anyhow::{anyhow, bail, Context};
+use axum::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use crate::utils::error::AppError;
+
+// Stockage des fichiers publics (avatars...) : disque local servi par l'API, ou bucket compatible S3/R2
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // Enregistre l'objet et renvoie son URL publique
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<String, AppError>;
+    async fn delete(&self, key: &str) -> Result<(), AppError>;
+    // Clé d'un objet à partir de son URL publique, si elle provient de ce stockage
+    fn key_for_url(&self, url: &str) -> Option<String>;
+}
+
+pub fn from_env(s3_config: Option<&str>, upload_dir: &str, api_url: &str) -> anyhow::Result<Arc<dyn Storage>> {
+    match s3_config {
+        Some(config) => Ok(Arc::new(S3Storage::parse(config)?)),
+        None => Ok(Arc::new(LocalStorage { root: PathBuf::from(upload_dir), public_url: format!("{}/uploads", api_url.trim_end_matches('/')) })),
+    }
+}
+
+pub struct LocalStorage { root: PathBuf, public_url: String }
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, bytes: Vec<u8>, _content_type: &str) -> Result<String, AppError> {
+        let path = self.root.join(key);
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await.map_err(|_| AppError::Internal)?;
+        }
+        tokio::fs::write(&path, bytes).await.map_err(|e| {
+            tracing::error!(error = %e, key, "cannot write upload");
+            AppError::Internal
+        })?;
+        Ok(format!("{}/{}", self.public_url, key))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), AppError> {
+        match tokio::fs::remove_file(self.root.join(key)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(AppError::Internal),
+            _ => Ok(()),
+        }
+    }
+
+    fn key_for_url(&self, url: &str) -> Option<String> { strip_base(url, &self.public_url) }
+}
+
+// S3_CONFIG="endpoint=https://<compte>.r2.cloudflarestorage.com,bucket=...,region=auto,access_key=...,secret_key=...,public_url=https://cdn.example.com"
+// Requêtes signées AWS Signature V4, adressage par chemin (compatible S3, R2, MinIO)
+pub struct S3Storage { endpoint: String, bucket: String, region: String, access_key: String, secret_key: String, public_url: String, http: reqwest::Client }
+
+impl S3Storage {
+    fn parse(config: &str) -> anyhow::Result<Self> {
+        let get = |name: &str| config.split(',').filter_map(|p| p.trim().split_once('=')).find(|(k, _)| *k == name).map(|(_, v)| v.trim().to_string());
+        let field = |name: &str| get(name).ok_or_else(|| anyhow!("S3_CONFIG is missing {name}"));
+        let endpoint = field("endpoint")?.trim_end_matches('/').to_string();
+        let bucket = field("bucket")?;
+        if !endpoint.starts_with("https://") && !endpoint.starts_with("http://") { bail!("S3_CONFIG endpoint must be an http(s) URL") }
+        let public_url = get("public_url").unwrap_or_else(|| format!("{endpoint}/{bucket}")).trim_end_matches('/').to_string();
+        let http = reqwest::Client::builder().timeout(std::time::Duration::from_secs(30)).build().context("cannot build S3 client")?;
+        Ok(S3Storage { endpoint, bucket, region: get("region").unwrap_or_else(|| "auto".to_string()), access_key: field("access_key")?, secret_key: field("secret_key")?, public_url, http })
+    }
+
+    fn signed_request(&self, method: reqwest::Method, key: &str, body: Vec<u8>, content_type: Option<&str>) -> Result<reqwest::RequestBuilder, AppError> {
+        let host = self.endpoint.split("://").nth(1).ok_or(AppError::Internal)?.to_string();
+        let path = format!("/{}/{}", self.bucket, key.split('/').map(|s| urlencoding::encode(s).into_owned()).collect::<Vec<_>>().join("/"));
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        let mut headers = vec![("host", host), ("x-amz-content-sha256", payload_hash.clone()), ("x-amz-date", amz_date.clone())];
+        if let Some(ct) = content_type { headers.push(("content-type", ct.to_string())) }
+        headers.sort_by(|a, b| a.0.cmp(b.0));
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{k}:{v}\n")).collect();
+        let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}", method.as_str(), path, canonical_headers, signed_headers, payload_hash);
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}", hex::encode(Sha256::digest(canonical_request.as_bytes())));
+        let signing_key = [date.as_str(), self.region.as_str(), "s3", "aws4_request"].iter().fold(format!("AWS4{}", self.secret_key).into_bytes(), |key, part| hmac_sha256(&key, part.as_bytes()));
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!("AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}", self.access_key);
+
+        let mut request = self.http.request(method, format!("{}{}", self.endpoint, path)).header("authorization", authorization);
+        for (k, v) in headers.into_iter().filter(|(k, _)| *k != "host") { request = request.header(k, v) }
+        Ok(request.body(body))
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<String, AppError> {
+        let response = self.signed_request(reqwest::Method::PUT, key, bytes, Some(content_type))?.send().await.map_err(|e| {
+            tracing::error!(error = %e, key, "S3 upload failed");
+            AppError::Internal
+        })?;
+        if !response.status().is_success() {
+            tracing::error!(status = %response.status(), key, "S3 upload rejected");
+            return Err(AppError::Internal);
+        }
+        Ok(format!("{}/{}", self.public_url, key))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), AppError> {
+        let response = self.signed_request(reqwest::Method::DELETE, key, Vec::new(), None)?.send().await.map_err(|_| AppError::Internal)?;
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND { return Err(AppError::Internal) }
+        Ok(())
+    }
+
+    fn key_for_url(&self, url: &str) -> Option<String> { strip_base(url, &self.public_url) }
+}
+
+fn strip_base(url: &str, base: &str) -> Option<String> {
+    url.strip_prefix(base)?.strip_prefix('/').filter(|k| !k.is_empty() && !k.split('/').any(|s| s == "..")).map(str::to_string)
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_base_returns_key_under_public_url() {
+        let base = "https://cdn.example.com/uploads";
+        assert_eq!(strip_base("https://cdn.example.com/uploads/avatars/u1/a.png", base).as_deref(), Some("avatars/u1/a.png"));
+        assert_eq!(strip_base("https://cdn.example.com/uploads/a.png", base).as_deref(), Some("a.png"));
+    }
+
+    #[test]
+    fn strip_base_rejects_foreign_or_traversing_urls() {
+        let base = "https://cdn.example.com/uploads";
+        for url in [
+            "https://evil.example.com/uploads/a.png",
+            "https://cdn.example.com/uploads-other/a.png",
+            "https://cdn.example.com/uploads",
+            "https://cdn.example.com/uploads/",
+            "https://cdn.example.com/uploads/../secrets/key",
+            "https://cdn.example.com/uploads/avatars/../../etc/passwd",
+        ] {
+            assert_eq!(strip_base(url, base), None, "{url}");
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+// Jeton opaque aléatoire (256 bits) transmis au client ; seul son hash est stocké en base
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,133 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+// TOTP RFC 6238 : HMAC-SHA1, pas de 30 secondes, 6 chiffres (paramètres attendus par les applications d'authentification)
+const STEP_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base32_encode(&bytes)
+}
+
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let label = urlencoding::encode(&format!("{issuer}:{account}")).into_owned();
+    format!("otpauth://totp/{label}?secret={secret}&issuer={}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECS}", urlencoding::encode(issuer))
+}
+
+// Vérifie le code sur le pas courant ±1 (dérive d'horloge) et renvoie le pas accepté,
+// que l'appelant mémorise pour refuser le rejeu d'un même code.
+pub fn verify(secret: &str, code: &str, unix_time: u64, last_used_step: Option<i64>) -> Option<i64> {
+    let key = base32_decode(secret)?;
+    let code = code.trim().replace(' ', "");
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) { return None }
+    let current = (unix_time / STEP_SECS) as i64;
+    (current - 1..=current + 1)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| constant_time_eq(format!("{:0width$}", hotp(&key, *step as u64), width = DIGITS as usize).as_bytes(), code.as_bytes()))
+}
+
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[19] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([digest[offset] & 0x7f, digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    binary % 10u32.pow(DIGITS)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            out.push(BASE32_ALPHABET[((buffer >> (bits - 5)) & 31) as usize] as char);
+            bits -= 5;
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 { out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char) }
+    out
+}
+
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in input.trim_end_matches('=').chars().filter(|c| !c.is_whitespace()) {
+        let value = BASE32_ALPHABET.iter().position(|a| *a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            out.push((buffer >> (bits - 8)) as u8);
+            bits -= 8;
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Clé des annexes de la RFC 4226 et de la RFC 6238 (SHA-1) : "12345678901234567890"
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn hotp_matches_rfc4226_vectors() {
+        let expected = [755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(RFC_KEY, counter as u64), *code, "counter {counter}");
+        }
+    }
+
+    #[test]
+    fn verify_matches_rfc6238_vectors() {
+        // Valeurs à 8 chiffres de la RFC tronquées aux 6 derniers, comme le fait l'application d'authentification
+        let secret = base32_encode(RFC_KEY);
+        for (time, code) in [(59, "287082"), (1111111109, "081804"), (1111111111, "050471"), (1234567890, "005924"), (2000000000, "279037"), (20000000000, "353130")] {
+            assert_eq!(verify(&secret, code, time, None), Some((time / STEP_SECS) as i64), "time {time}");
+        }
+    }
+
+    #[test]
+    fn base32_round_trip() {
+        assert_eq!(base32_encode(RFC_KEY), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        for len in 0..=20 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+            assert_eq!(base32_decode(&base32_encode(&data)), Some(data));
+        }
+        assert_eq!(base32_decode("gezd gnbv gy3t qojq gezd gnbv gy3t qojq===="), Some(RFC_KEY.to_vec()));
+        assert_eq!(base32_decode("GEZD1"), None);
+        assert_eq!(generate_secret().len(), 32);
+    }
+
+    #[test]
+    fn rejects_replayed_and_out_of_window_codes() {
+        let secret = base32_encode(RFC_KEY);
+        let now = 1111111111;
+        let step = (now / STEP_SECS) as i64;
+        let code = format!("{:06}", hotp(RFC_KEY, step as u64));
+        assert_eq!(verify(&secret, &code, now, Some(step - 1)), Some(step));
+        assert_eq!(verify(&secret, &code, now, Some(step)), None);
+        assert_eq!(verify(&secret, &code, now, Some(step + 1)), None);
+
+        // Dérive d'horloge : le pas précédent est accepté tant qu'il n'a pas été utilisé
+        let previous = format!("{:06}", hotp(RFC_KEY, (step - 1) as u64));
+        assert_eq!(verify(&secret, &previous, now, None), Some(step - 1));
+        assert_eq!(verify(&secret, &previous, now, Some(step - 1)), None);
+        // Hors de la fenêtre ±1 pas
+        let stale = format!("{:06}", hotp(RFC_KEY, (step - 2) as u64));
+        assert_eq!(verify(&secret, &stale, now, None), None);
+        assert_eq!(verify(&secret, "12345", now, None), None);
+    }
+}
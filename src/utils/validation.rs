@@ -0,0 +1,97 @@
+use std::borrow::Cow;
+use validator::{ValidationError, ValidationErrors};
+use crate::utils::error::{AppError, FieldErrors};
+
+// Valeurs de l'énumération Postgres pcsoft_experience
+pub const PCSOFT_EXPERIENCE: &[&str] = &["beginner", "intermediate", "expert"];
+
+pub fn normalize_email(email: &str) -> String { email.trim().to_lowercase() }
+
+pub fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else { return false };
+    !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.') && !email.contains(char::is_whitespace) && email.len() <= 254
+}
+
+// Adresse jugée après normalisation, comme elle sera enregistrée
+pub fn email(value: &str) -> Result<(), ValidationError> {
+    if is_valid_email(&normalize_email(value)) { return Ok(()) }
+    Err(error("email", "Invalid email address"))
+}
+
+// Refuse une valeur faite uniquement d'espaces
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if !value.trim().is_empty() { return Ok(()) }
+    Err(error("blank", "Must not be empty"))
+}
+
+// Les validateurs suivants acceptent la chaîne vide, qui efface le champ
+
+pub fn http_url(value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() { return Ok(()) }
+    match url::Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.host_str().is_some() => Ok(()),
+        _ => Err(error("url", "Must be a valid http(s) URL")),
+    }
+}
+
+// Numéro international E.164 : "+" puis 2 à 15 chiffres, sans 0 initial
+pub fn e164_phone(value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() { return Ok(()) }
+    let digits = value.strip_prefix('+').unwrap_or_default();
+    if (2..=15).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()) && !digits.starts_with('0') { return Ok(()) }
+    Err(error("phone", "Must be an international phone number such as +33612345678"))
+}
+
+pub fn pcsoft_experience(value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() || PCSOFT_EXPERIENCE.contains(&value) { return Ok(()) }
+    Err(error("enum", "Must be one of: beginner, intermediate, expert"))
+}
+
+pub fn error(code: &'static str, message: impl Into<Cow<'static, str>>) -> ValidationError {
+    ValidationError::new(code).with_message(message.into())
+}
+
+// Erreurs du validateur -> 422 avec un message par champ
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        let fields: FieldErrors = errors.field_errors().into_iter().map(|(field, errors)| {
+            let messages = errors.iter().map(|e| e.message.as_deref().map(str::to_string).unwrap_or_else(|| format!("Invalid value ({})", e.code))).collect();
+            (field.to_string(), messages)
+        }).collect();
+        AppError::Validation(fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_http_urls_only() {
+        for url in ["", "https://example.com", "http://example.com/path?q=1", "https://sub.example.co.uk:8443/a"] {
+            assert!(http_url(url).is_ok(), "{url}");
+        }
+        for url in ["example.com", "ftp://example.com", "javascript:alert(1)", "https://", "mailto:a@b.c"] {
+            assert!(http_url(url).is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn accepts_e164_phone_numbers_only() {
+        for phone in ["", "+33612345678", "+12025550123", "+44", "+123456789012345"] {
+            assert!(e164_phone(phone).is_ok(), "{phone}");
+        }
+        for phone in ["0612345678", "+0612345678", "+3", "+1234567890123456", "+33 6 12 34 56 78", "+33-612345678", "33612345678", "+"] {
+            assert!(e164_phone(phone).is_err(), "{phone}");
+        }
+    }
+
+    #[test]
+    fn validates_emails_after_normalisation() {
+        assert_eq!(normalize_email("  Jane.Doe@Example.COM "), "jane.doe@example.com");
+        assert!(email(" Jane.Doe@Example.COM ").is_ok());
+        for value in ["jane", "@example.com", "jane@example", "jane@.example.com", "jane@example.com.", "ja ne@example.com"] {
+            assert!(email(value).is_err(), "{value}");
+        }
+    }
+}